@@ -1,51 +1,374 @@
 use super::{Environment, ResultType, RuntimeError, Value};
+use graphic;
 use std::fs;
+use std::io::{self, Write};
 
 pub fn make(env: &mut Environment, args: &[Value]) -> ResultType {
     if let Value::String(ref name) = args[0] {
         env.current_frame().locals.insert(name.clone(), args[1].clone());
         Ok(Value::Nothing)
     } else {
-        Err(RuntimeError(format!("invalid argument: {:?}", args[1])))
+        Err(RuntimeError::new(format!("invalid argument: {:?}", args[1])))
     }
 }
 
+/// Like `make`, but only allowed inside of a function, so it never touches a
+/// global variable of the same name
+pub fn local(env: &mut Environment, args: &[Value]) -> ResultType {
+    if env.current_frame().is_global {
+        return Err(RuntimeError::new("local can only be used inside of a function".to_owned()));
+    }
+    make(env, args)
+}
+
 pub fn global(env: &mut Environment, args: &[Value]) -> ResultType {
     if let Value::String(ref name) = args[0] {
         env.global_frame().locals.insert(name.clone(), args[1].clone());
         Ok(Value::Nothing)
     } else {
-        Err(RuntimeError(format!("invalid argument: {:?}", args[1])))
+        Err(RuntimeError::new(format!("invalid argument: {:?}", args[1])))
     }
 }
 
+pub fn setlistitem(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref name),
+              arg Value::Number(index),
+              arg ref value, =>
+    {
+        // Change the variable where it is found, like a lookup would
+        let frame = if env.current_frame().locals.contains_key(name) {
+            env.current_frame()
+        } else {
+            env.global_frame()
+        };
+        let list = match frame.locals.get_mut(name) {
+            Some(&mut Value::List(ref mut list)) => list,
+            Some(other) => return Err(RuntimeError::new(format!("{} is a {}, not a list",
+                                                           name, other.type_string()))),
+            None => return Err(RuntimeError::new(format!("Variable {} not found", name))),
+        };
+        if index < 0. || index as usize >= list.len() {
+            return Err(RuntimeError::new(format!("Index out of bounds: {} >= {}", index, list.len())));
+        }
+        list[index as usize] = value.clone();
+        Ok(Value::Nothing)
+    })
+}
+
 pub fn screenshot(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref name), => {
         let shot = env.get_turtle().get_screen().screenshot();
-        let mut file = match fs::File::create(name) {
-            Ok(f) => f,
-            Err(e) => return Err(RuntimeError(format!("{}", e))),
-        };
-        match shot.save(&mut file, ::image::ImageFormat::PNG) {
-            Ok(()) => Ok(Value::Nothing),
-            Err(e) => Err(RuntimeError(format!("{}", e))),
-        }
+        save_image(&shot, name)
     })
 }
 
+pub fn screenshotcrop(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let shot = env.get_turtle().get_screen().screenshot_cropped();
+        save_image(&shot, name)
+    })
+}
+
+/// Save the image with the given name, the format is chosen based on the file
+/// extension
+fn save_image(image: &::image::DynamicImage, name: &str) -> ResultType {
+    let mut file = match fs::File::create(name) {
+        Ok(f) => f,
+        Err(e) => return Err(RuntimeError::new(format!("{}", e))),
+    };
+    let format = graphic::image_format_for(name);
+    let result = if format == ::image::ImageFormat::JPEG {
+        // JPEG has no alpha channel
+        ::image::DynamicImage::ImageRgb8(image.to_rgb()).save(&mut file, format)
+    } else {
+        image.save(&mut file, format)
+    };
+    match result {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError::new(format!("{}", e))),
+    }
+}
+
 pub fn prompt(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref prompt_string), => {
         // What?
         let input = super::super::super::readline::readline(prompt_string);
         match input {
             Some(i) => Ok(Value::String(i)),
-            None => Err(RuntimeError("No input to get".to_owned())),
+            None => Err(RuntimeError::new("No input to get".to_owned())),
+        }
+    })
+}
+
+pub fn inputnumber(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref prompt_string), => {
+        loop {
+            let input = super::super::super::readline::readline(prompt_string);
+            match input {
+                Some(i) => match i.trim().parse::<f32>() {
+                    Ok(num) => return Ok(Value::Number(num)),
+                    Err(_) => {
+                        let written = writeln!(env.output(), "Not a number: {}", i.trim());
+                        if let Err(e) = written {
+                            return Err(RuntimeError::new(format!("{}", e)));
+                        }
+                    },
+                },
+                None => return Err(RuntimeError::new("No input to get".to_owned())),
+            }
+        }
+    })
+}
+
+pub fn click(env: &mut Environment, _: &[Value]) -> ResultType {
+    match env.get_turtle().get_screen().wait_for_click() {
+        Some((x, y)) => Ok(Value::List(vec![Value::Number(x), Value::Number(y)])),
+        None => Err(RuntimeError::new("Window closed while waiting for a click".to_owned())),
+    }
+}
+
+pub fn setfont(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref path), => {
+        match env.get_turtle().get_screen().set_font(path) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError::new(format!("{}", e))),
+        }
+    })
+}
+
+pub fn bgimage(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref path), => {
+        match env.get_turtle().get_screen().set_background_image(path, false) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError::new(format!("{}", e))),
+        }
+    })
+}
+
+pub fn nobgimage(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.get_turtle().get_screen().clear_background_image();
+    Ok(Value::Nothing)
+}
+
+pub fn fitview(env: &mut Environment, _: &[Value]) -> ResultType {
+    let screen = env.get_turtle().get_screen();
+    screen.fit_to_drawing();
+    screen.draw_and_update();
+    Ok(Value::Nothing)
+}
+
+pub fn settitle(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref title), => {
+        env.get_turtle().get_screen().set_title(title);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn shapecount(env: &mut Environment, _: &[Value]) -> ResultType {
+    let count = env.get_turtle().get_screen().shape_count();
+    Ok(Value::Number(count as f32))
+}
+
+pub fn bbox(env: &mut Environment, _: &[Value]) -> ResultType {
+    let (min_x, min_y, max_x, max_y) = env.get_turtle().get_screen().bounding_box();
+    Ok(Value::List(vec![Value::Number(min_x), Value::Number(min_y),
+                        Value::Number(max_x), Value::Number(max_y)]))
+}
+
+pub fn screenwidth(env: &mut Environment, _: &[Value]) -> ResultType {
+    let (width, _) = env.get_turtle().get_screen().get_dimensions();
+    Ok(Value::Number(width as f32))
+}
+
+pub fn screenheight(env: &mut Environment, _: &[Value]) -> ResultType {
+    let (_, height) = env.get_turtle().get_screen().get_dimensions();
+    Ok(Value::Number(height as f32))
+}
+
+pub fn grid(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(spacing), => {
+        if !(spacing > 0. && spacing.is_finite()) {
+            return Err(RuntimeError::new("The grid spacing has to be a positive number"
+                                         .to_owned()));
+        }
+        let screen = env.get_turtle().get_screen();
+        screen.show_grid(spacing);
+        screen.draw_and_update();
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn nogrid(env: &mut Environment, _: &[Value]) -> ResultType {
+    let screen = env.get_turtle().get_screen();
+    screen.hide_grid();
+    screen.draw_and_update();
+    Ok(Value::Nothing)
+}
+
+pub fn keypressed(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let key = match graphic::key_from_name(name) {
+            Some(k) => k,
+            None => return Err(RuntimeError::new(format!("unknown key: {:?}", name))),
+        };
+        let screen = env.get_turtle().get_screen();
+        screen.handle_events();
+        Ok(Value::Number(if screen.is_key_pressed(key) { 1. } else { 0. }))
+    })
+}
+
+pub fn sleep(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(seconds), => {
+        if seconds < 0. {
+            return Err(RuntimeError::new("Can't sleep for a negative time".to_owned()));
         }
+        env.get_turtle().get_screen().sleep(seconds);
+        try!(env.check_closed());
+        Ok(Value::Nothing)
     })
 }
 
+/// Print the given function names with their argument counts, wrapped to
+/// lines of at most 80 characters
+fn print_functions(output: &mut Write, title: &str, functions: &[(String, i32)])
+                   -> io::Result<()> {
+    try!(writeln!(output, "{}:", title));
+    let mut line = String::new();
+    for &(ref name, arg_count) in functions {
+        let entry = format!("{} ({})", name.to_lowercase(), arg_count);
+        if !line.is_empty() && line.len() + entry.len() + 2 > 80 {
+            try!(writeln!(output, "  {}", line));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push_str(", ");
+        }
+        line.push_str(&entry);
+    }
+    if !line.is_empty() {
+        try!(writeln!(output, "  {}", line));
+    }
+    Ok(())
+}
+
+pub fn help(env: &mut Environment, _: &[Value]) -> ResultType {
+    // The built-in functions are listed by category, in the order of
+    // functions::categories
+    let mut builtin: Vec<_> = super::categories().into_iter()
+        .map(|(category, functions)| (category, functions, Vec::new()))
+        .collect();
+    let mut learned = Vec::new();
+    for (name, arg_count, is_native) in env.function_signatures() {
+        let category = if is_native {
+            builtin.iter().position(|&(_, ref functions, _)| functions.contains_key(&name))
+        } else {
+            None
+        };
+        match category {
+            Some(i) => builtin[i].2.push((name, arg_count)),
+            None => learned.push((name, arg_count)),
+        }
+    }
+    let builtin: Vec<_> = builtin.into_iter()
+        .map(|(category, _, entries)| (category, entries))
+        .collect();
+    match print_help(env.output(), &builtin, &learned) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError::new(format!("{}", e))),
+    }
+}
+
+fn print_help(output: &mut Write, builtin: &[(&str, Vec<(String, i32)>)],
+              learned: &[(String, i32)]) -> io::Result<()> {
+    try!(writeln!(output, "Available functions, the number of arguments is given in parenthesis."));
+    for &(category, ref functions) in builtin {
+        if !functions.is_empty() {
+            try!(print_functions(output, category, functions));
+        }
+    }
+    if !learned.is_empty() {
+        try!(print_functions(output, "Learned functions", learned));
+    }
+    Ok(())
+}
+
+pub fn assert(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg ref condition, arg Value::String(ref message), => {
+        if condition.boolean() {
+            Ok(Value::Nothing)
+        } else {
+            Err(RuntimeError::tagged("assert", format!("Assertion failed: {}", message)))
+        }
+    })
+}
+
+pub fn asserteq(env: &mut Environment, args: &[Value]) -> ResultType {
+    if env.values_equal(&args[0], &args[1]) {
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError::tagged("assert", format!("Assertion failed: {} is not equal to {}",
+                                                   args[0], args[1])))
+    }
+}
+
 pub fn throw(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref error_desc), => {
-        Err(RuntimeError(error_desc.clone()))
+        Err(RuntimeError::new(error_desc.clone()))
+    })
+}
+
+pub fn throwtype(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref tag), arg Value::String(ref error_desc), => {
+        Err(RuntimeError::tagged(tag.clone(), error_desc.clone()))
+    })
+}
+
+pub fn rethrow(env: &mut Environment, _: &[Value]) -> ResultType {
+    match env.handled_error.clone() {
+        Some(error) => Err(error),
+        None => Err(RuntimeError::new("rethrow can only be used in the else part of a try")),
+    }
+}
+
+pub fn save(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match env.save_definitions(name) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError::new(format!("{}", e))),
+        }
     })
 }
+
+pub fn load(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match env.load_definitions(name) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError::new(format!("{}", e))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use environ::Environment;
+    use environ::value::Value;
+    use graphic::TurtleScreen;
+    use turtle::Turtle;
+
+    fn environment() -> Environment {
+        let screen = TurtleScreen::new((100, 100), "Rurtle test");
+        Environment::new(Turtle::new(screen))
+    }
+
+    #[test]
+    #[ignore] // Needs a display to open the window, run with --ignored
+    fn test_local_keeps_global() {
+        let mut env = environment();
+        env.eval_source("make \"size\" 50
+                         learn inner do local \"size\" 10 return :size end
+                         make \"result\" inner").unwrap();
+        assert_eq!(env.get_variable("size"), Some(Value::Number(50.)));
+        assert_eq!(env.get_variable("result"), Some(Value::Number(10.)));
+    }
+}
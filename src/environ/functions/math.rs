@@ -0,0 +1,112 @@
+use super::{AngleMode, Environment, ResultType, RuntimeError, Value};
+
+pub fn min(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(a), arg Value::Number(b), => {
+        Ok(Value::Number(a.min(b)))
+    })
+}
+
+pub fn max(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(a), arg Value::Number(b), => {
+        Ok(Value::Number(a.max(b)))
+    })
+}
+
+/// Integer division, rounding towards negative infinity
+pub fn div(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(a), arg Value::Number(b), => {
+        if b == 0. {
+            return Err(RuntimeError::new("Division by zero".to_owned()));
+        }
+        Ok(Value::Number((a / b).floor()))
+    })
+}
+
+/// Remainder of `div`, it has the same sign as the divisor so that
+/// `a = (div a b) * b + (mod a b)`
+pub fn modulo(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(a), arg Value::Number(b), => {
+        if b == 0. {
+            return Err(RuntimeError::new("Division by zero".to_owned()));
+        }
+        Ok(Value::Number(a - (a / b).floor() * b))
+    })
+}
+
+/// Helper function to fold a list of numbers with the given function. Returns
+/// an error if the list is empty or contains something else than numbers.
+fn fold_numbers<F>(values: &[Value], f: F) -> ResultType
+    where F: Fn(f32, f32) -> f32
+{
+    let mut result = None;
+    for value in values {
+        if let Value::Number(num) = *value {
+            result = Some(match result {
+                Some(accum) => f(accum, num),
+                None => num,
+            });
+        } else {
+            return Err(RuntimeError::new(format!("invalid argument: {:?}", value)));
+        }
+    }
+    match result {
+        Some(num) => Ok(Value::Number(num)),
+        None => Err(RuntimeError::new("The list is empty".to_owned())),
+    }
+}
+
+pub fn minlist(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref values), => {
+        fold_numbers(values, f32::min)
+    })
+}
+
+pub fn maxlist(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref values), => {
+        fold_numbers(values, f32::max)
+    })
+}
+
+pub fn pi(_: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(::std::f32::consts::PI))
+}
+
+pub fn e(_: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(::std::f32::consts::E))
+}
+
+/// Convert the angle from the environment's angle mode to radians
+fn to_radians(env: &Environment, angle: f32) -> f32 {
+    match env.get_angle_mode() {
+        AngleMode::Degrees => ::std::f32::consts::PI * angle / 180.,
+        AngleMode::Radians => angle,
+    }
+}
+
+pub fn sin(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(angle), => {
+        Ok(Value::Number(to_radians(env, angle).sin()))
+    })
+}
+
+pub fn cos(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(angle), => {
+        Ok(Value::Number(to_radians(env, angle).cos()))
+    })
+}
+
+pub fn tan(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(angle), => {
+        Ok(Value::Number(to_radians(env, angle).tan()))
+    })
+}
+
+pub fn degrees(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.set_angle_mode(AngleMode::Degrees);
+    Ok(Value::Nothing)
+}
+
+pub fn radians(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.set_angle_mode(AngleMode::Radians);
+    Ok(Value::Nothing)
+}
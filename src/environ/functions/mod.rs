@@ -7,18 +7,21 @@
 // at some point and clippy doesn't like that
 #![cfg_attr(feature = "linted", allow(identity_op))]
 
-pub use super::{Environment, Function, ResultType, RuntimeError};
+pub use super::{AngleMode, Environment, Function, ResultType, RuntimeError};
 pub use super::value::Value;
 use super::Function::Native;
 use std::collections::HashMap;
+use std::io::Write;
 
 // Example function.
 //
 // The first argument is the `Environment` in which the function is called, the
 // second argument is are the (already evaluated) arguments.
-fn print(_: &mut Environment, args: &[Value]) -> ResultType {
-    println!("{}", args[0]);
-    Ok(Value::Nothing)
+fn print(env: &mut Environment, args: &[Value]) -> ResultType {
+    match writeln!(env.output(), "{}", args[0]) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError::new(format!("{}", e))),
+    }
 }
 
 /// Helper macro to extract the given arguments.
@@ -28,7 +31,7 @@ fn print(_: &mut Environment, args: &[Value]) -> ResultType {
 /// the => expr part which specifies what should happen with the arguments.
 ///
 /// If a argument can't be matched with the given pattern, a
-/// `Err(RuntimeError(..))` is returned.
+/// `Err(RuntimeError::new(..))` is returned.
 ///
 /// # Example
 ///
@@ -55,7 +58,7 @@ macro_rules! get_args {
             if let $p = *arg {
                 get_args!(index $ind+1, $args, $(arg $ps,)* => $b)
             } else {
-                Err(RuntimeError(format!("invalid argument: {:?}", arg)))
+                Err(RuntimeError::new(format!("invalid argument: {:?}", arg)))
             }
         }
     };
@@ -67,6 +70,7 @@ mod turtle;
 mod env;
 mod types;
 mod string;
+mod math;
 
 /// A helpful macro to construct a `HashMap`
 macro_rules! map {
@@ -81,34 +85,95 @@ macro_rules! map {
 
 /// Return a HashMap of the built-in functions
 pub fn default_functions() -> HashMap<String, Function> {
-    map!{
-        "PRINT" => Native(1, print),
+    let mut result = HashMap::new();
+    for (_, functions) in categories() {
+        result.extend(functions);
+    }
+    result
+}
 
+/// Return the built-in functions grouped by category, in the order in which
+/// `help` lists them
+pub fn categories() -> Vec<(&'static str, HashMap<String, Function>)> {
+    vec![("General", map!{
+        "PRINT" => Native(1, print),
+        "HELP" => Native(0, env::help),
+    }), ("Turtle and screen", map!{
         // Turtle control/draw functions
         "FORWARD" => Native(1, turtle::forward),
         "BACKWARD" => Native(1, turtle::backward),
         "LEFT" => Native(1, turtle::left),
         "RIGHT" => Native(1, turtle::right),
+        "MOVEBY" => Native(2, turtle::moveby),
+        "POLYGON" => Native(2, turtle::polygon),
+        "CURVE" => Native(4, turtle::curve),
         "COLOR" => Native(3, turtle::color),
+        "COLORA" => Native(4, turtle::colora),
+        "GRADIENT" => Native(3, turtle::gradient),
+        "COLORHEX" => Native(1, turtle::colorhex),
         "BGCOLOR" => Native(3, turtle::bgcolor),
+        "BGCOLORA" => Native(4, turtle::bgcolora),
+        "LINESTYLE" => Native(1, turtle::linestyle),
         "CLEAR" => Native(0, turtle::clear),
+        "CLEARTEXT" => Native(0, turtle::cleartext),
+        "CLEARLINES" => Native(0, turtle::clearlines),
+        "RESET" => Native(0, turtle::reset),
         "PENDOWN" => Native(0, turtle::pendown),
         "PENUP" => Native(0, turtle::penup),
+        "ISPENDOWN" => Native(0, turtle::ispendown),
         "HOME" => Native(0, turtle::home),
         "REALIGN" => Native(1, turtle::realign),
+        "SETHEADING" => Native(1, turtle::setheading),
         "HIDE" => Native(0, turtle::hide),
         "SHOW" => Native(0, turtle::show),
         "WRITE" => Native(1, turtle::write),
+        "WRITEAT" => Native(4, turtle::writeat),
+        "WRITESIZE" => Native(2, turtle::writesize),
+        "ALIGN" => Native(1, turtle::align),
+        "SETFONT" => Native(1, env::setfont),
+        "BGIMAGE" => Native(1, env::bgimage),
+        "NOBGIMAGE" => Native(0, env::nobgimage),
+        "SETTITLE" => Native(1, env::settitle),
+        "FITVIEW" => Native(0, env::fitview),
+        "SHAPECOUNT" => Native(0, env::shapecount),
+        "BBOX" => Native(0, env::bbox),
+        "SCREENWIDTH" => Native(0, env::screenwidth),
+        "SCREENHEIGHT" => Native(0, env::screenheight),
+        "GRID" => Native(1, env::grid),
+        "NOGRID" => Native(0, env::nogrid),
+        "STAMP" => Native(0, turtle::stamp),
+        "SHAPE" => Native(1, turtle::shape),
+        "TURTLESIZE" => Native(1, turtle::turtlesize),
         "FLOOD" => Native(0, turtle::flood),
-
+        "FILLAT" => Native(2, turtle::fillat),
+        "GETPIXEL" => Native(2, turtle::getpixel),
+        "FLOODTOLERANCE" => Native(1, turtle::floodtolerance),
+        "UNDO" => Native(0, turtle::undo),
+        "TRACE" => Native(1, turtle::trace),
+        "BEGINBATCH" => Native(0, turtle::beginbatch),
+        "ENDBATCH" => Native(0, turtle::endbatch),
+    }), ("Environment", map!{
         // Environment functions to set variables
         "MAKE" => Native(2, env::make),
+        "LOCAL" => Native(2, env::local),
         "GLOBAL" => Native(2, env::global),
+        "SETLISTITEM" => Native(3, env::setlistitem),
         // Other environment functions
         "SCREENSHOT" => Native(1, env::screenshot),
+        "SCREENSHOTCROP" => Native(1, env::screenshotcrop),
         "PROMPT" => Native(1, env::prompt),
+        "INPUTNUMBER" => Native(1, env::inputnumber),
+        "CLICK" => Native(0, env::click),
+        "KEYPRESSED" => Native(1, env::keypressed),
+        "SLEEP" => Native(1, env::sleep),
         "THROW" => Native(1, env::throw),
-
+        "ASSERT" => Native(2, env::assert),
+        "ASSERTEQ" => Native(2, env::asserteq),
+        "THROWTYPE" => Native(2, env::throwtype),
+        "RETHROW" => Native(0, env::rethrow),
+        "SAVE" => Native(1, env::save),
+        "LOAD" => Native(1, env::load),
+    }), ("Lists and types", map!{
         // Haskellesque names
         "HEAD" => Native(1, types::head),
         "TAIL" => Native(1, types::tail),
@@ -118,18 +183,50 @@ pub fn default_functions() -> HashMap<String, Function> {
         // other list functions
         "LENGTH" => Native(1, types::length), // also works for strings
         "ISEMPTY" => Native(1, types::isempty),
-        "GETINDEX" => Native(2, types::getindex),
-        "FIND" => Native(2, types::find),
+        "GETINDEX" => Native(2, types::getindex), // also works for strings
+        "FIND" => Native(2, types::find), // also works for strings
+        "COUNT" => Native(2, types::count), // also works for strings
+        "CONTAINS" => Native(2, types::contains), // also works for strings
+        "SLICE" => Native(3, types::slice), // also works for strings
+        "MAP" => Native(2, types::map),
+        "FILTER" => Native(2, types::filter),
+        "REDUCE" => Native(3, types::reduce),
+        "FLATTEN" => Native(1, types::flatten),
+        "DEPTH" => Native(1, types::depth),
         // conversion
+        // NOT is a keyword, but the function is still available for MAP and
+        // FILTER
         "NOT" => Native(1, types::not),
         "TONUMBER" => Native(1, types::tonumber),
         "TOSTRING" => Native(1, types::tostring),
+        "TYPEOF" => Native(1, types::typeof_),
         "NOTHING" => Native(0, types::nothing),
-
+    }), ("Strings", map!{
         // String manipulating functions
         "REPLACE" => Native(3, string::replace),
-        "CONTAINS" => Native(2, string::contains),
         "CHARS" => Native(1, string::chars),
+        "STRINGREVERSE" => Native(1, string::stringreverse),
         "SPLIT" => Native(2, string::split),
-    }
+        "STARTSWITH" => Native(2, string::startswith),
+        "ENDSWITH" => Native(2, string::endswith),
+        "FORMAT" => Native(2, string::format),
+    }), ("Math", map!{
+        // Math functions
+        "MIN" => Native(2, math::min),
+        "MAX" => Native(2, math::max),
+        "MINLIST" => Native(1, math::minlist),
+        "MAXLIST" => Native(1, math::maxlist),
+        "DIV" => Native(2, math::div),
+        "MOD" => Native(2, math::modulo),
+        // Trigonometry, the angle is given in degrees unless RADIANS was
+        // called
+        "SIN" => Native(1, math::sin),
+        "COS" => Native(1, math::cos),
+        "TAN" => Native(1, math::tan),
+        "DEGREES" => Native(0, math::degrees),
+        "RADIANS" => Native(0, math::radians),
+        // Constants
+        "PI" => Native(0, math::pi),
+        "E" => Native(0, math::e),
+    })]
 }
@@ -10,18 +10,15 @@ pub fn replace(_: &mut Environment, args: &[Value]) -> ResultType {
     })
 }
 
-pub fn contains(_: &mut Environment, args: &[Value]) -> ResultType {
-    get_args!(args,
-              arg Value::String(ref original),
-              arg Value::String(ref pattern), =>
-    {
-        Ok(Value::Number(if original.contains(pattern) { 1. } else { 0. }))
+pub fn chars(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref string), => {
+        Ok(Value::List(string.chars().map(|c| Value::String(c.to_string())).collect()))
     })
 }
 
-pub fn chars(_: &mut Environment, args: &[Value]) -> ResultType {
+pub fn stringreverse(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref string), => {
-        Ok(Value::List(string.chars().map(|c| Value::String(c.to_string())).collect()))
+        Ok(Value::String(string.chars().rev().collect()))
     })
 }
 
@@ -33,3 +30,37 @@ pub fn split(_: &mut Environment, args: &[Value]) -> ResultType {
         Ok(Value::List(string.split(pattern).map(|s| Value::String(s.to_owned())).collect()))
     })
 }
+
+pub fn startswith(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref string),
+              arg Value::String(ref prefix), =>
+    {
+        Ok(Value::Number(if string.starts_with(prefix.as_str()) { 1. } else { 0. }))
+    })
+}
+
+pub fn endswith(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref string),
+              arg Value::String(ref suffix), =>
+    {
+        Ok(Value::Number(if string.ends_with(suffix.as_str()) { 1. } else { 0. }))
+    })
+}
+
+/// The most decimals that `format` accepts, more can't be represented by an
+/// f32 anyway
+const MAX_DECIMALS: f32 = 32.;
+
+pub fn format(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(number),
+              arg Value::Number(decimals), =>
+    {
+        if decimals < 0. || decimals > MAX_DECIMALS || decimals.fract() != 0. {
+            return Err(RuntimeError::new(format!("invalid number of decimals: {}", decimals)));
+        }
+        Ok(Value::String(format!("{:.*}", decimals as usize, number)))
+    })
+}
@@ -1,4 +1,6 @@
 use super::{Environment, ResultType, RuntimeError, Value};
+use graphic::{LineStyle, Alignment, ShapeKind, TurtleShape};
+use graphic::color::Color;
 
 pub fn forward(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
@@ -28,6 +30,32 @@ pub fn right(env: &mut Environment, args: &[Value]) -> ResultType {
     })
 }
 
+pub fn moveby(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(dx), arg Value::Number(dy), => {
+        env.turtle.move_by(dx, dy);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn polygon(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(sides), arg Value::Number(length), => {
+        if sides < 3. || sides.fract() != 0. {
+            return Err(RuntimeError::new("A polygon needs a whole number of at least 3 sides"
+                                    .to_owned()));
+        }
+        env.turtle.polygon(sides as u32, length);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn curve(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(cx), arg Value::Number(cy), arg Value::Number(x),
+              arg Value::Number(y), => {
+        env.turtle.curve_to((cx, cy), (x, y));
+        Ok(Value::Nothing)
+    })
+}
+
 pub fn color(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args,
               arg Value::Number(a),
@@ -38,6 +66,90 @@ pub fn color(env: &mut Environment, args: &[Value]) -> ResultType {
               })
 }
 
+/// Convert a list `[r g b]` or `[r g b a]` to a color
+fn list_to_color(value: &Value) -> Result<Color, RuntimeError> {
+    if let Value::List(ref values) = *value {
+        let mut channels = Vec::new();
+        for channel in values {
+            match *channel {
+                Value::Number(x) => channels.push(x),
+                _ => return Err(RuntimeError::new(format!("Invalid color: {}", value))),
+            }
+        }
+        match channels.len() {
+            3 => return Ok((channels[0], channels[1], channels[2], 1.0)),
+            4 => return Ok((channels[0], channels[1], channels[2], channels[3])),
+            _ => (),
+        }
+    }
+    Err(RuntimeError::new(format!("Invalid color: {}", value)))
+}
+
+pub fn gradient(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg ref from, arg ref to, arg Value::Number(length), => {
+        let from = try!(list_to_color(from));
+        let to = try!(list_to_color(to));
+        if !(length > 0.) {
+            return Err(RuntimeError::new("The length of a gradient has to be positive"));
+        }
+        env.turtle.set_gradient(from, to, length);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn linestyle(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let style = match name.to_lowercase().as_ref() {
+            "solid" => LineStyle::Solid,
+            "dashed" => LineStyle::Dashed,
+            "dotted" => LineStyle::Dotted,
+            _ => return Err(RuntimeError::new(format!("Unknown line style: {}", name))),
+        };
+        env.turtle.set_line_style(style);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn colora(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(r),
+              arg Value::Number(g),
+              arg Value::Number(b),
+              arg Value::Number(a), => {
+                  env.turtle.set_color_alpha(r, g, b, a);
+                  Ok(Value::Nothing)
+              })
+}
+
+/// Parse a color given as "#RRGGBB" or "#RRGGBBAA"
+fn parse_hex_color(input: &str) -> Option<Color> {
+    if !input.starts_with('#') {
+        return None;
+    }
+    let digits = &input[1..];
+    if !(digits.len() == 6 || digits.len() == 8) || !digits.chars().all(|c| c.is_digit(16)) {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).unwrap() as f32 / 255.
+    };
+    let alpha = if digits.len() == 8 { channel(3) } else { 1. };
+    Some((channel(0), channel(1), channel(2), alpha))
+}
+
+pub fn colorhex(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref hex), => {
+        match parse_hex_color(hex) {
+            Some((r, g, b, a)) => {
+                env.turtle.set_color_alpha(r, g, b, a);
+                Ok(Value::Nothing)
+            },
+            None => Err(RuntimeError::new(format!(
+                "invalid color: {:?}, expected \"#RRGGBB\" or \"#RRGGBBAA\"", hex))),
+        }
+    })
+}
+
 pub fn bgcolor(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args,
               arg Value::Number(r),
@@ -48,16 +160,46 @@ pub fn bgcolor(env: &mut Environment, args: &[Value]) -> ResultType {
               })
 }
 
+pub fn bgcolora(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(r),
+              arg Value::Number(g),
+              arg Value::Number(b),
+              arg Value::Number(a), => {
+                  env.turtle.set_background_alpha(r, g, b, a);
+                  Ok(Value::Nothing)
+              })
+}
+
 pub fn clear(env: &mut Environment, _: &[Value]) -> ResultType {
     env.turtle.clear();
     Ok(Value::Nothing)
 }
 
+pub fn cleartext(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_kind(ShapeKind::Text);
+    Ok(Value::Nothing)
+}
+
+pub fn clearlines(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_kind(ShapeKind::Line);
+    Ok(Value::Nothing)
+}
+
+pub fn reset(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.reset();
+    Ok(Value::Nothing)
+}
+
 pub fn pendown(env: &mut Environment, _: &[Value]) -> ResultType {
     env.turtle.pen_down();
     Ok(Value::Nothing)
 }
 
+pub fn ispendown(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(if env.turtle.is_pen_down() { 1. } else { 0. }))
+}
+
 pub fn penup(env: &mut Environment, _: &[Value]) -> ResultType {
     env.turtle.pen_up();
     Ok(Value::Nothing)
@@ -75,6 +217,15 @@ pub fn realign(env: &mut Environment, args: &[Value]) -> ResultType {
     })
 }
 
+/// Like realign, but with Logo's convention of positive angles counting
+/// clockwise
+pub fn setheading(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(x), => {
+        env.turtle.set_orientation(-x);
+        Ok(Value::Nothing)
+    })
+}
+
 pub fn hide(env: &mut Environment, _: &[Value]) -> ResultType {
     env.turtle.hide();
     Ok(Value::Nothing)
@@ -92,7 +243,137 @@ pub fn write(env: &mut Environment, args: &[Value]) -> ResultType {
     })
 }
 
+pub fn writesize(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref s), arg Value::Number(size), => {
+        let alignment = env.turtle.get_alignment();
+        env.turtle.write_ex(s, size, alignment);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn writeat(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(x),
+              arg Value::Number(y),
+              arg Value::Number(angle),
+              arg Value::String(ref s), =>
+    {
+        env.turtle.write_at((x, y), angle, s);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn align(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let alignment = match name.to_lowercase().as_ref() {
+            "left" => Alignment::Left,
+            "center" => Alignment::Center,
+            "right" => Alignment::Right,
+            _ => return Err(RuntimeError::new(format!("Unknown alignment: {}", name))),
+        };
+        env.turtle.set_alignment(alignment);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn stamp(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.stamp();
+    Ok(Value::Nothing)
+}
+
+pub fn shape(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let shape = match &name.to_lowercase() as &str {
+            "ferris" => TurtleShape::Ferris,
+            "triangle" => TurtleShape::Triangle,
+            "classic" => TurtleShape::Classic,
+            _ => match env.get_turtle().get_screen().load_turtle_image(name) {
+                Ok(shape) => shape,
+                Err(e) => return Err(RuntimeError::new(format!("{}", e))),
+            },
+        };
+        env.turtle.set_shape(shape);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn turtlesize(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(size), => {
+        if !(size > 0.) {
+            return Err(RuntimeError::new("The turtle size has to be positive"));
+        }
+        env.turtle.set_turtle_size(size);
+        Ok(Value::Nothing)
+    })
+}
+
 pub fn flood(env: &mut Environment, _: &[Value]) -> ResultType {
-    env.turtle.flood();
+    if env.turtle.flood() {
+        Ok(Value::Nothing)
+    } else {
+        Err(fill_error(env))
+    }
+}
+
+/// Return the error for a fill that didn't happen
+fn fill_error(env: &mut Environment) -> RuntimeError {
+    if env.get_turtle().get_screen().is_closed() {
+        RuntimeError::new("Interrupted, the window has been closed")
+    } else {
+        RuntimeError::new("Can't fill outside of the visible canvas")
+    }
+}
+
+pub fn getpixel(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(x), arg Value::Number(y), => {
+        match env.turtle.get_screen().get_pixel((x, y)) {
+            Some((r, g, b, _)) => Ok(Value::List(vec![Value::Number(r), Value::Number(g),
+                                                      Value::Number(b)])),
+            None => Err(RuntimeError::new("The point is outside of the visible canvas".to_owned())),
+        }
+    })
+}
+
+pub fn fillat(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(x), arg Value::Number(y), => {
+        if env.turtle.flood_at((x, y)) {
+            Ok(Value::Nothing)
+        } else {
+            Err(fill_error(env))
+        }
+    })
+}
+
+pub fn floodtolerance(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(tolerance), => {
+        if !(tolerance >= 0. && tolerance <= 1.) {
+            return Err(RuntimeError::new(format!("Tolerance has to be between 0 and 1, got {}",
+                                                 tolerance)));
+        }
+        env.turtle.set_fill_tolerance(tolerance);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn undo(env: &mut Environment, _: &[Value]) -> ResultType {
+    if env.turtle.undo() {
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError::new("Nothing to undo".to_owned()))
+    }
+}
+
+pub fn beginbatch(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.set_immediate_draw(false);
+    Ok(Value::Nothing)
+}
+
+pub fn endbatch(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.set_immediate_draw(true);
+    Ok(Value::Nothing)
+}
+
+pub fn trace(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.set_pen_trace(args[0].boolean());
     Ok(Value::Nothing)
 }
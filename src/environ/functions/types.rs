@@ -24,7 +24,7 @@ pub fn length(_: &mut Environment, args: &[Value]) -> ResultType {
     match args[0] {
         Value::List(ref l) => Ok(Value::Number(l.len() as f32)),
         Value::String(ref s) => Ok(Value::Number(s.len() as f32)),
-        ref val => Err(RuntimeError(format!("Invalid argument: {}", val))),
+        ref val => Err(RuntimeError::new(format!("Invalid argument: {}", val))),
     }
 }
 
@@ -35,33 +35,197 @@ pub fn isempty(_: &mut Environment, args: &[Value]) -> ResultType {
 }
 
 pub fn getindex(_: &mut Environment, args: &[Value]) -> ResultType {
+    let idx = match args[1] {
+        Value::Number(n) if n >= 0. && n.fract() == 0. => n as usize,
+        Value::Number(n) => return Err(RuntimeError::new(format!("Invalid index: {}", n))),
+        ref val => return Err(RuntimeError::new(format!("Invalid argument: {}", val))),
+    };
+    match args[0] {
+        Value::List(ref values) => {
+            if idx >= values.len() {
+                Err(RuntimeError::new(format!("Index out of bounds: {} >= {}", idx, values.len())))
+            } else {
+                Ok(values[idx].clone())
+            }
+        },
+        Value::String(ref string) => {
+            match string.chars().nth(idx) {
+                Some(c) => Ok(Value::String(c.to_string())),
+                None => Err(RuntimeError::new(format!("Index out of bounds: {} >= {}",
+                                                 idx, string.chars().count()))),
+            }
+        },
+        ref val => Err(RuntimeError::new(format!("Invalid argument: {}", val))),
+    }
+}
+
+pub fn find(env: &mut Environment, args: &[Value]) -> ResultType {
+    match (&args[0], &args[1]) {
+        (&Value::List(ref values), needle) => {
+            match values.iter().position(|hay| env.values_equal(hay, needle)) {
+                Some(i) => Ok(Value::Number(i as f32)),
+                None => Ok(Value::Number(-1.)),
+            }
+        },
+        (&Value::String(ref haystack), &Value::String(ref needle)) => {
+            match haystack.find(needle.as_str()) {
+                // find gives us the byte offset, but we want the character index
+                Some(offset) => Ok(Value::Number(haystack[..offset].chars().count() as f32)),
+                None => Ok(Value::Number(-1.)),
+            }
+        },
+        _ => Err(RuntimeError::new(format!("Invalid argument: {}", args[0]))),
+    }
+}
+
+pub fn count(env: &mut Environment, args: &[Value]) -> ResultType {
+    match (&args[0], &args[1]) {
+        (&Value::List(ref values), needle) => {
+            let count = values.iter().filter(|hay| env.values_equal(hay, needle)).count();
+            Ok(Value::Number(count as f32))
+        },
+        (&Value::String(ref haystack), &Value::String(ref needle)) => {
+            if needle.is_empty() {
+                return Err(RuntimeError::new("Can't count the empty string"));
+            }
+            // matches() only finds non-overlapping occurences
+            Ok(Value::Number(haystack.matches(needle.as_str()).count() as f32))
+        },
+        _ => Err(RuntimeError::new(format!("Invalid argument: {}", args[0]))),
+    }
+}
+
+pub fn contains(env: &mut Environment, args: &[Value]) -> ResultType {
+    let found = match (&args[0], &args[1]) {
+        (&Value::List(ref values), needle) => {
+            values.iter().any(|hay| env.values_equal(hay, needle))
+        },
+        (&Value::String(ref haystack), &Value::String(ref needle)) => {
+            haystack.contains(needle.as_str())
+        },
+        _ => return Err(RuntimeError::new(format!("Invalid argument: {}", args[0]))),
+    };
+    Ok(Value::Number(if found { 1. } else { 0. }))
+}
+
+/// Resolve a (possibly negative) slice index against the given length. Negative
+/// indices count from the end, out-of-range indices are clamped.
+fn slice_index(index: f32, len: usize) -> usize {
+    let index = index as i64;
+    let len = len as i64;
+    let resolved = if index < 0 { len + index } else { index };
+    if resolved < 0 {
+        0
+    } else if resolved > len {
+        len as usize
+    } else {
+        resolved as usize
+    }
+}
+
+pub fn slice(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (start, end) = match (&args[1], &args[2]) {
+        (&Value::Number(start), &Value::Number(end)) => (start, end),
+        _ => return Err(RuntimeError::new(format!("Invalid slice bounds: {} {}", args[1], args[2]))),
+    };
+    match args[0] {
+        Value::List(ref values) => {
+            let (start, end) = (slice_index(start, values.len()), slice_index(end, values.len()));
+            if start >= end {
+                Ok(Value::List(Vec::new()))
+            } else {
+                Ok(Value::List(values[start..end].to_vec()))
+            }
+        },
+        Value::String(ref string) => {
+            let len = string.chars().count();
+            let (start, end) = (slice_index(start, len), slice_index(end, len));
+            if start >= end {
+                Ok(Value::String(String::new()))
+            } else {
+                Ok(Value::String(string.chars().skip(start).take(end - start).collect()))
+            }
+        },
+        ref val => Err(RuntimeError::new(format!("Invalid argument: {}", val))),
+    }
+}
+
+pub fn map(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args,
-              arg Value::List(ref values),
-              arg Value::Number(n), =>
+              arg Value::String(ref function),
+              arg Value::List(ref values), =>
     {
-        let idx = n as usize;
-        if idx >= values.len() {
-            Err(RuntimeError(format!("Index out of bounds: {} >= {}", idx, values.len())))
-        } else {
-            Ok(values[idx].clone())
+        let mut result = Vec::with_capacity(values.len());
+        for value in values {
+            result.push(try!(env.call_function(function, vec![value.clone()])));
         }
+        Ok(Value::List(result))
     })
 }
 
-pub fn find(_: &mut Environment, args: &[Value]) -> ResultType {
-    if let Value::List(ref values) = args[0] {
-        let needle = &args[1];
-        for (i, hay) in values.iter().enumerate() {
-            if hay == needle {
-                return Ok(Value::Number(i as f32))
+pub fn filter(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref function),
+              arg Value::List(ref values), =>
+    {
+        let mut result = Vec::new();
+        for value in values {
+            if try!(env.call_function(function, vec![value.clone()])).boolean() {
+                result.push(value.clone());
             }
         }
-        Ok(Value::Number(-1.))
-    } else {
-        Err(RuntimeError(format!("Invalid argument: {}", args[0])))
+        Ok(Value::List(result))
+    })
+}
+
+pub fn reduce(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref function),
+              arg ref initial,
+              arg Value::List(ref values), =>
+    {
+        let mut accumulator = initial.clone();
+        for value in values {
+            accumulator = try!(env.call_function(function, vec![accumulator, value.clone()]));
+        }
+        Ok(accumulator)
+    })
+}
+
+/// Helper function to append the elements of a (possibly nested) list to the
+/// result, recursing into every sublist
+fn flatten_into(values: &[Value], result: &mut Vec<Value>) {
+    for value in values {
+        match *value {
+            Value::List(ref sublist) => flatten_into(sublist, result),
+            ref other => result.push(other.clone()),
+        }
+    }
+}
+
+pub fn flatten(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref values), => {
+        let mut result = Vec::new();
+        flatten_into(values, &mut result);
+        Ok(Value::List(result))
+    })
+}
+
+/// Helper function to compute the nesting depth of a value, 0 for anything that
+/// is not a list
+fn nesting_depth(value: &Value) -> usize {
+    match *value {
+        Value::List(ref values) => 1 + values.iter().map(nesting_depth).max().unwrap_or(0),
+        _ => 0,
     }
 }
 
+pub fn depth(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg ref value @ Value::List(_), => {
+        Ok(Value::Number(nesting_depth(value) as f32))
+    })
+}
+
 pub fn not(_: &mut Environment, args: &[Value]) -> ResultType {
     let as_boolean = args[0].boolean();
     Ok(Value::Number(if as_boolean { 0. } else { 1. }))
@@ -70,12 +234,19 @@ pub fn not(_: &mut Environment, args: &[Value]) -> ResultType {
 // Type conversion functions
 
 pub fn tonumber(_: &mut Environment, args: &[Value]) -> ResultType {
-    get_args!(args, arg Value::String(ref string), => {
-        match string.parse::<f32>() {
+    match args[0] {
+        Value::Number(num) => Ok(Value::Number(num)),
+        Value::String(ref string) => match string.parse::<f32>() {
             Ok(num) => Ok(Value::Number(num)),
-            Err(e) => Err(RuntimeError(format!("{}", e))),
-        }
-    })
+            Err(e) => Err(RuntimeError::new(format!("cannot convert {:?} to number: {}", string, e))),
+        },
+        ref other => Err(RuntimeError::new(format!("cannot convert {} to number",
+                                              other.type_string()))),
+    }
+}
+
+pub fn typeof_(_: &mut Environment, args: &[Value]) -> ResultType {
+    Ok(Value::String(args[0].type_string().to_owned()))
 }
 
 pub fn tostring(_: &mut Environment, args: &[Value]) -> ResultType {
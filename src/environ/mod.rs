@@ -8,17 +8,66 @@ pub mod functions;
 pub mod value;
 pub mod stack;
 use self::value::Value;
-use super::parse::ast::{Node, AddOp, MulOp, CompOp};
+use super::parse::ast::{Node, AddOp, MulOp, CompOp, LogicOp};
 use super::turtle;
-use std::collections::HashMap;
+use super::lex::MetaToken;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
 use std::fmt;
-
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::rc::Rc;
+use std::thread;
+use std::time;
+
+/// Default tolerance when comparing two numbers for equality
+pub const DEFAULT_EPSILON: f32 = 1e-6;
+
+/// Minimum time in milliseconds between two polls of the window's events in
+/// `check_interrupted`, polling in every iteration slows tight loops down
+const POLL_INTERVAL_MS: u64 = 16;
+
+/// An error that occured while executing Rurtle code. Errors thrown by Rurtle
+/// code may carry a type (tag) so that they can be told apart.
 #[derive(Debug, Clone)]
-pub struct RuntimeError(String);
+pub struct RuntimeError {
+    message: String,
+    tag: Option<String>,
+}
+
+impl RuntimeError {
+    /// Create a new error with the given message and no type
+    pub fn new<S: Into<String>>(message: S) -> RuntimeError {
+        RuntimeError {
+            message: message.into(),
+            tag: None,
+        }
+    }
+
+    /// Create a new error with the given type and message
+    pub fn tagged<S: Into<String>, T: Into<String>>(tag: T, message: S) -> RuntimeError {
+        RuntimeError {
+            message: message.into(),
+            tag: Some(tag.into()),
+        }
+    }
+
+    /// Return the error message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Return the type of the error, if it has one
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(|t| t.as_ref())
+    }
+}
 
 impl ::std::fmt::Display for RuntimeError {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        fmt.pad(&self.0)
+        fmt.pad(&self.message)
     }
 }
 
@@ -59,6 +108,21 @@ impl Clone for Function {
     }
 }
 
+impl Function {
+    /// Return the number of arguments that the function takes
+    pub fn arg_count(&self) -> i32 {
+        match *self {
+            Function::Native(i, _) => i,
+            Function::Defined(ref node) => {
+                match *node {
+                    Node::LearnStatement(_, ref args, _) => args.len() as i32,
+                    _ => panic!("Function node is not a LearnStatement"),
+                }
+            },
+        }
+    }
+}
+
 /// Helper function to get a pointer without needing to type the type
 fn pointer<T>(x: &T) -> *const T { x as *const T }
 
@@ -87,9 +151,43 @@ macro_rules! framed {
     }
 }
 
+/// A buffer that can be written to while another handle to it is kept, used
+/// by `Environment::capture_output`
+#[derive(Clone)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The unit in which the trigonometric functions take and return angles. The
+/// turtle's movement functions always use degrees.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AngleMode {
+    Degrees,
+    Radians,
+}
+
 pub struct Environment {
     stack: Vec<stack::Frame>,
     turtle: turtle::Turtle,
+    angle_mode: AngleMode,
+    epsilon: f32,
+    output: Box<Write>,
+    // When the window's events were last handled by check_interrupted
+    last_poll: time::Instant,
+    // The error caught by the TRY whose exception block is currently running,
+    // used by RETHROW
+    handled_error: Option<RuntimeError>,
+    // The original source code of top-level LEARN statements together with
+    // the definition it was parsed to, used to save definitions verbatim
+    learn_sources: HashMap<String, (Node, String)>,
 }
 
 impl Environment {
@@ -98,6 +196,12 @@ impl Environment {
         Environment {
             stack: stack::new_stack(),
             turtle: turtle,
+            angle_mode: AngleMode::Degrees,
+            epsilon: DEFAULT_EPSILON,
+            output: Box::new(io::stdout()),
+            last_poll: time::Instant::now(),
+            handled_error: None,
+            learn_sources: HashMap::new(),
         }
     }
 
@@ -105,6 +209,66 @@ impl Environment {
         &mut self.turtle
     }
 
+    /// Set the unit that the trigonometric functions use
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+    }
+
+    /// Return the unit that the trigonometric functions use
+    pub fn get_angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    /// Set how much two numbers may differ to still be considered equal by
+    /// `=` and `<>`. This deviates from strict IEEE equality on purpose, so
+    /// that e.g. `sin 30 * 2 = 1` is true despite rounding errors. Ordering
+    /// comparisons are not affected.
+    pub fn set_epsilon(&mut self, epsilon: f32) {
+        self.epsilon = epsilon;
+    }
+
+    /// Return the tolerance used when comparing numbers for equality
+    pub fn get_epsilon(&self) -> f32 {
+        self.epsilon
+    }
+
+    /// Return true if the two values are equal. Numbers (also inside of lists)
+    /// are compared with the tolerance set by `set_epsilon`.
+    pub fn values_equal(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (&Value::Number(x), &Value::Number(y)) => (x - y).abs() <= self.epsilon,
+            (&Value::List(ref xs), &Value::List(ref ys)) => {
+                xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| self.values_equal(x, y))
+            },
+            _ => a == b,
+        }
+    }
+
+    /// Set where the output of `print` and similar functions goes. The default
+    /// is stdout.
+    pub fn set_output(&mut self, output: Box<Write>) {
+        self.output = output;
+    }
+
+    /// Return the writer that functions should print their output to
+    pub fn output(&mut self) -> &mut Write {
+        &mut *self.output
+    }
+
+    /// Call the given function and collect everything that is printed in the
+    /// meantime. Returns the function's result and the output, afterwards the
+    /// previous output is used again.
+    pub fn capture_output<F, T>(&mut self, function: F) -> (T, String)
+        where F: FnOnce(&mut Environment) -> T
+    {
+        let buffer = SharedBuffer(Rc::new(RefCell::new(Vec::new())));
+        let previous = mem::replace(&mut self.output, Box::new(buffer.clone()));
+        let result = function(self);
+        self.output = previous;
+        let output = String::from_utf8_lossy(&buffer.0.borrow()).into_owned();
+        (result, output)
+    }
+
     /// Return a map mapping the function name to the argument count. Useful for
     /// passing it to `Parser::parse`
     pub fn function_arg_count(&self) -> HashMap<String, i32> {
@@ -115,22 +279,35 @@ impl Environment {
         for stack_frame in &self.stack {
             for mini_frame in &stack_frame.functions {
                 for (name, function) in mini_frame {
-                    let count = match *function {
-                        Function::Native(i, _) => i,
-                        Function::Defined(ref node) => {
-                            match *node {
-                                Node::LearnStatement(_, ref args, _) => args.len() as i32,
-                                _ => panic!("Function node is not a LearnStatement"),
-                            }
-                        },
-                    };
-                    result.insert(name.clone(), count);
+                    result.insert(name.clone(), function.arg_count());
                 }
             }
         }
         result
     }
 
+    /// Return the name, the argument count and whether it is a native function
+    /// for every function that is in scope, sorted by name.
+    pub fn function_signatures(&self) -> Vec<(String, i32, bool)> {
+        // Like in function_arg_count, functions in "tighter" frames overwrite
+        // the more general ones
+        let mut signatures = BTreeMap::new();
+        for stack_frame in &self.stack {
+            for mini_frame in &stack_frame.functions {
+                for (name, function) in mini_frame {
+                    let is_native = match *function {
+                        Function::Native(..) => true,
+                        Function::Defined(..) => false,
+                    };
+                    signatures.insert(name.clone(), (function.arg_count(), is_native));
+                }
+            }
+        }
+        signatures.into_iter()
+            .map(|(name, (arg_count, is_native))| (name, arg_count, is_native))
+            .collect()
+    }
+
     fn find_function(&self, name: &str) -> Option<&Function> {
         for stack_frame in self.stack.iter().rev() {
             for mini_frame in stack_frame.functions.iter().rev() {
@@ -151,17 +328,120 @@ impl Environment {
             Ok(t) => t,
             Err(e) => return Err(Box::new(e)),
         };
+        let sources = find_learn_sources(source, &tokens);
         let mut parser = parse::Parser::new(tokens, self.function_arg_count());
         let tree = match parser.parse() {
             Ok(n) => n.flatten(),
             Err(e) => return Err(Box::new(e)),
         };
+        self.remember_learn_sources(&tree, sources);
         match self.eval(&tree) {
             Ok(v) => return Ok(v),
             Err(e) => return Err(Box::new(e)),
         };
     }
 
+    /// Store the source code of the top-level `LEARN` statements in `tree`,
+    /// `sources` maps the function names to their source code.
+    fn remember_learn_sources(&mut self, tree: &Node, mut sources: HashMap<String, String>) {
+        let statements: Vec<&Node> = match *tree {
+            Node::StatementList(ref statements) => statements.iter().collect(),
+            ref node => vec![node],
+        };
+        for statement in statements {
+            if let Node::LearnStatement(ref name, _, _) = *statement {
+                if let Some(source) = sources.remove(name) {
+                    self.learn_sources.insert(name.clone(), (statement.clone(), source));
+                }
+            }
+        }
+    }
+
+    /// Write the source code of every function that was defined via `LEARN` in
+    /// the global frame to the given file. Native functions are skipped. The
+    /// file can be read back using `load_definitions`.
+    ///
+    /// Functions are written as they were entered, including comments and
+    /// formatting. Only functions whose source is unknown are written from
+    /// their parsed form.
+    pub fn save_definitions(&self, path: &str) -> io::Result<()> {
+        // Use a BTreeMap so that the output is sorted and definitions in inner
+        // frames override the outer ones
+        let mut definitions = BTreeMap::new();
+        for mini_frame in &self.stack[0].functions {
+            for (name, function) in mini_frame {
+                if let Function::Defined(ref node) = *function {
+                    definitions.insert(name, node);
+                }
+            }
+        }
+        let mut file = try!(fs::File::create(path));
+        for (name, node) in definitions {
+            match self.learn_sources.get(name) {
+                Some(&(ref original, ref source)) if original == node =>
+                    try!(writeln!(file, "{}", source)),
+                _ => try!(writeln!(file, "{}", node)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read function definitions from the given file and add them to the
+    /// global frame. The file may only contain `LEARN` statements, as written
+    /// by `save_definitions`.
+    pub fn load_definitions(&mut self, path: &str) -> Result<(), Box<Error>> {
+        use super::lex::{self, Token};
+        use super::parse;
+        let mut source = String::new();
+        let mut file = try!(fs::File::open(path));
+        try!(file.read_to_string(&mut source));
+        let tokens = try!(lex::tokenize(&source));
+        let sources = find_learn_sources(&source, &tokens);
+        // Announce every definition to the parser beforehand, so that functions
+        // may call each other regardless of their order in the file
+        let mut functions = self.function_arg_count();
+        {
+            let mut iter = tokens.iter().map(|meta| &meta.token);
+            while let Some(token) = iter.next() {
+                if let Token::KeyLearn = *token {
+                    if let Some(&Token::Word(ref name)) = iter.next() {
+                        let mut count = 0;
+                        for token in iter.by_ref() {
+                            match *token {
+                                Token::Colon => count += 1,
+                                Token::Word(_) => (),
+                                _ => break,
+                            }
+                        }
+                        functions.insert(name.to_uppercase(), count);
+                    }
+                }
+            }
+        }
+        let mut parser = parse::Parser::new(tokens, functions);
+        let tree = try!(parser.parse()).flatten();
+        self.remember_learn_sources(&tree, sources);
+        let definitions = match tree {
+            Node::StatementList(statements) => statements,
+            node => vec![node],
+        };
+        for definition in &definitions {
+            match *definition {
+                Node::LearnStatement(..) => (),
+                _ => return Err(Box::new(RuntimeError::new(
+                    format!("{} contains statements other than function definitions", path)))),
+            }
+        }
+        for definition in definitions {
+            let name = match definition {
+                Node::LearnStatement(ref name, _, _) => name.clone(),
+                _ => unreachable!(),
+            };
+            self.global_frame().functions[0].insert(name, Function::Defined(definition));
+        }
+        Ok(())
+    }
+
     /// Evaluate the given AST node
     pub fn eval(&mut self, node: &Node) -> ResultType {
         use super::parse::ast::Node::*;
@@ -173,14 +453,20 @@ impl Environment {
                 self.eval_statement_list(nodes),
             IfStatement(ref condition, ref true_body, ref false_body) =>
                 self.eval_if_statement(condition, true_body, false_body),
-            RepeatStatement(ref num, ref body) =>
-                self.eval_repeat_statement(num, body),
+            RepeatStatement(ref num, ref index, ref body) =>
+                self.eval_repeat_statement(num, index.as_ref(), body),
             WhileStatement(ref condition, ref body) =>
                 self.eval_while_statement(condition, body),
             ref learn_statement @ LearnStatement(..) =>
                 self.eval_learn_statement(learn_statement),
             Comparison(ref a, op, ref b) =>
                 self.eval_comparison(a, op, b),
+            LogicalOp(ref a, op, ref b) =>
+                self.eval_logical_op(a, op, b),
+            Not(ref operand) =>
+                self.eval_not(operand),
+            Negate(ref operand) =>
+                self.eval_negate(operand),
             Addition(ref start, ref values) =>
                 self.eval_addition(start, values),
             Multiplication(ref start, ref values) =>
@@ -195,6 +481,8 @@ impl Environment {
                 self.eval_assignment(name, value),
             List(ref elements) =>
                 self.eval_list(elements),
+            ListComprehension(ref name, ref from, ref to, ref body) =>
+                self.eval_list_comprehension(name, from, to, body),
             StringLiteral(ref string) =>
                 Ok(Value::String(string.clone())),
             Number(num) =>
@@ -224,25 +512,91 @@ impl Environment {
         Ok(Value::Nothing)
     }
 
-    fn eval_repeat_statement(&mut self, num: &Node, body: &Node) -> ResultType {
+    fn eval_repeat_statement(&mut self, num: &Node, index: Option<&String>, body: &Node)
+                             -> ResultType {
         let num = try!(self.eval(num));
         if let Value::Number(num) = num {
-            for _ in 0..num as i32 {
-                try!(framed!(self, self.eval(body)));
-            }
-            Ok(Value::Nothing)
+            self.with_loop_variable(index.map(|name| &name[..]), |env| {
+                for i in 0..num as i32 {
+                    try!(env.check_interrupted());
+                    if let Some(name) = index {
+                        env.current_frame().locals.insert(name.clone(), Value::Number(i as f32));
+                    }
+                    try!(framed!(env, env.eval(body)));
+                }
+                Ok(Value::Nothing)
+            })
         } else {
-            Err(RuntimeError("repeat count has to be a number".to_owned()))
+            Err(RuntimeError::new("repeat count has to be a number".to_owned()))
         }
     }
 
     fn eval_while_statement(&mut self, condition: &Node, body: &Node) -> ResultType {
         while try!(self.eval(condition)).boolean() {
+            try!(self.check_interrupted());
             try!(framed!(self, self.eval(body)));
         }
         Ok(Value::Nothing)
     }
 
+    /// Run `f` and afterwards give the local variable `name` the value it had
+    /// before, so that a loop variable neither clobbers a variable of the
+    /// caller nor stays defined after the loop.
+    fn with_loop_variable<F>(&mut self, name: Option<&str>, f: F) -> ResultType
+        where F: FnOnce(&mut Environment) -> ResultType
+    {
+        let saved = name.map(|name| self.current_frame().save_local(name));
+        let result = f(self);
+        if let Some(saved) = saved {
+            self.current_frame().restore_local(saved);
+        }
+        result
+    }
+
+    /// Write where the screenshots taken with the screen's `screenshot_key`
+    /// have been saved to the output
+    pub fn report_screenshots(&mut self) {
+        for result in self.turtle.get_screen().take_screenshot_results() {
+            // If writing to the output fails, there's nowhere to report it
+            let _ = match result {
+                Ok(path) => writeln!(self.output, "Saved screenshot as {}", path.display()),
+                Err(e) => writeln!(self.output, "Saving the screenshot failed: {}", e),
+            };
+        }
+    }
+
+    /// Handle the window's events and return an error if the window has been
+    /// closed. This is used in loops and function calls so that they can be
+    /// stopped by closing the window. The events are handled at most every `POLL_INTERVAL_MS`
+    /// milliseconds. If the screen is paused, this blocks until it is resumed.
+    fn check_interrupted(&mut self) -> Result<(), RuntimeError> {
+        if self.last_poll.elapsed() < time::Duration::from_millis(POLL_INTERVAL_MS) {
+            return Ok(());
+        }
+        self.last_poll = time::Instant::now();
+        self.turtle.get_screen().handle_events();
+        self.report_screenshots();
+        if self.turtle.get_screen().is_paused() {
+            self.turtle.get_screen().draw_and_update();
+            while self.turtle.get_screen().is_paused() && !self.turtle.get_screen().is_closed() {
+                thread::sleep(time::Duration::from_millis(1000 / 15));
+                self.turtle.get_screen().handle_events();
+                self.report_screenshots();
+            }
+        }
+        self.check_closed()
+    }
+
+    /// Return an error if the window has been closed, so that the running
+    /// code is interrupted
+    fn check_closed(&mut self) -> Result<(), RuntimeError> {
+        if self.turtle.get_screen().is_closed() {
+            Err(RuntimeError::new("Interrupted, the window has been closed"))
+        } else {
+            Ok(())
+        }
+    }
+
     fn eval_learn_statement(&mut self, statement: &Node) -> ResultType {
         if let Node::LearnStatement(ref name, _, _) = *statement {
             self.current_frame().functions.last_mut().unwrap()
@@ -257,8 +611,25 @@ impl Environment {
         let result = framed!(self, self.eval(normal));
         match result {
             Ok(_) => Ok(Value::Nothing),
-            Err(_) => {
-                framed!(self, self.eval(exception))
+            Err(error) => {
+                // Make the error message and type available to the exception
+                // block, an error without type has an empty string as type.
+                // Afterwards the variables get their previous values again.
+                let tag = error.tag().unwrap_or("").to_owned();
+                let saved = {
+                    let frame = self.current_frame();
+                    let saved = (frame.save_local("error"), frame.save_local("errortype"));
+                    frame.locals.insert("error".to_owned(), Value::String(error.message.clone()));
+                    frame.locals.insert("errortype".to_owned(), Value::String(tag));
+                    saved
+                };
+                let outer_error = mem::replace(&mut self.handled_error, Some(error));
+                let result = framed!(self, self.eval(exception));
+                self.handled_error = outer_error;
+                let frame = self.current_frame();
+                frame.restore_local(saved.0);
+                frame.restore_local(saved.1);
+                result
             },
         }
     }
@@ -266,16 +637,50 @@ impl Environment {
     fn eval_comparison(&mut self, a: &Node, op: CompOp, b: &Node) -> ResultType {
         let value_a = try!(self.eval(a));
         let value_b = try!(self.eval(b));
+        // Equality uses the same tolerance as ASSERTEQ, see values_equal
+        match op {
+            CompOp::Equal | CompOp::NotEqual => {
+                let equal = self.values_equal(&value_a, &value_b);
+                let result = if op == CompOp::Equal { equal } else { !equal };
+                return Ok(Value::Number(if result { 1.0 } else { 0.0 }));
+            },
+            _ => (),
+        }
         let compare = value_a.partial_cmp(&value_b);
         match compare {
             Some(ordering) => Ok(Value::Number({
                 if op.matches(&ordering) { 1.0 } else { 0.0 }
             })),
-            None => Err(RuntimeError(format!("Can't compare {} and {}",
+            None => Err(RuntimeError::new(format!("Can't compare {} and {}",
                                              value_a.type_string(), value_b.type_string()))),
         }
     }
 
+    fn eval_logical_op(&mut self, a: &Node, op: LogicOp, b: &Node) -> ResultType {
+        let value_a = try!(self.eval(a)).boolean();
+        // Short-circuit evaluation, b is only evaluated if the result depends
+        // on it
+        match op {
+            LogicOp::And if !value_a => return Ok(Value::Number(0.0)),
+            LogicOp::Or if value_a => return Ok(Value::Number(1.0)),
+            _ => (),
+        }
+        let value_b = try!(self.eval(b)).boolean();
+        Ok(Value::Number(if value_b { 1.0 } else { 0.0 }))
+    }
+
+    fn eval_not(&mut self, operand: &Node) -> ResultType {
+        let value = try!(self.eval(operand));
+        Ok(Value::Number(if value.boolean() { 0.0 } else { 1.0 }))
+    }
+
+    fn eval_negate(&mut self, operand: &Node) -> ResultType {
+        match try!(self.eval(operand)) {
+            Value::Number(num) => Ok(Value::Number(-num)),
+            value => Err(RuntimeError::new(format!("Can't negate {}", value.type_string()))),
+        }
+    }
+
     fn eval_addition(&mut self, start: &Node, values: &[(AddOp, Node)]) -> ResultType {
         let mut accum = try!(self.eval(start));
         for &(op, ref value) in values.iter() {
@@ -286,7 +691,7 @@ impl Environment {
             };
             accum = match result {
                 Some(v) => v,
-                None => return Err(RuntimeError(
+                None => return Err(RuntimeError::new(
                     format!("Can't add/subtract {} and {}",
                             accum.type_string(), value.type_string()))),
             }
@@ -304,7 +709,7 @@ impl Environment {
             };
             accum = match result {
                 Some(v) => v,
-                None => return Err(RuntimeError(
+                None => return Err(RuntimeError::new(
                     format!("Can't multiply/divide {} and {}",
                             accum.type_string(), value.type_string()))),
             }
@@ -313,11 +718,22 @@ impl Environment {
     }
 
     fn eval_func_call(&mut self, name: &str, arg_nodes: &[Node]) -> ResultType {
+        let args: Vec<Value> = try!(arg_nodes.iter().map(|a| self.eval(a)).collect());
+        self.call_function(name, args)
+    }
+
+    /// Call the function with the given name and the given (already evaluated)
+    /// arguments. Returns an error if the function doesn't exist or if the
+    /// number of arguments doesn't match.
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> ResultType {
         let function = match self.find_function(&name.to_uppercase()) {
             Some(f) => f.clone(),
-            None => return Err(RuntimeError(format!("function {} not found", name))),
+            None => return Err(RuntimeError::new(format!("function {} not found", name))),
         };
-        let args: Vec<Value> = try!(arg_nodes.iter().map(|a| self.eval(a)).collect());
+        if function.arg_count() as usize != args.len() {
+            return Err(RuntimeError::new(format!("function {} takes {} argument(s), got {}",
+                                            name, function.arg_count(), args.len())));
+        }
         match function {
             Function::Native(_, ref f) => {
                 f(self, &args)
@@ -336,6 +752,9 @@ impl Environment {
                              args: Vec<Value>, body: &Node)
                              -> ResultType
     {
+        // Checked here as well, so that recursive functions without loops can
+        // be interrupted and paused too
+        try!(self.check_interrupted());
         let mut frame = stack::Frame::default();
         frame.fn_name = name.into();
         for (name, value) in arg_names.iter().zip(args) {
@@ -353,7 +772,7 @@ impl Environment {
 
     fn eval_return_statement(&mut self, value: &Node) -> ResultType {
         if self.current_frame().is_global {
-            return Err(RuntimeError("Return not in a function".to_owned()));
+            return Err(RuntimeError::new("Return not in a function".to_owned()));
         }
         let value = try!(self.eval(value));
         self.current_frame().return_value = Some(value);
@@ -375,10 +794,30 @@ impl Environment {
         Ok(Value::List(result))
     }
 
+    fn eval_list_comprehension(&mut self, name: &str, from: &Node, to: &Node, body: &Node)
+                               -> ResultType {
+        let (from, to) = match (try!(self.eval(from)), try!(self.eval(to))) {
+            (Value::Number(from), Value::Number(to)) => (from, to),
+            _ => return Err(RuntimeError::new("FOR needs numbers as bounds")),
+        };
+        // Count with an integer, adding 1.0 repeatedly loses precision
+        let count = if to >= from { (to - from).floor() as u64 + 1 } else { 0 };
+        self.with_loop_variable(Some(name), |env| {
+            let mut result = Vec::new();
+            for step in 0..count {
+                try!(env.check_interrupted());
+                let i = from + step as f32;
+                env.current_frame().locals.insert(name.into(), Value::Number(i));
+                result.push(try!(env.eval(body)));
+            }
+            Ok(Value::List(result))
+        })
+    }
+
     fn eval_variable(&mut self, name: &str) -> ResultType {
         match self.get_variable(name) {
             Some(value) => Ok(value),
-            None => Err(RuntimeError(format!("Variable {} not found", name))),
+            None => Err(RuntimeError::new(format!("Variable {} not found", name))),
         }
     }
 
@@ -410,14 +849,59 @@ impl Environment {
     /// it is not defined there, the global namespace will be searched. If the
     /// variable is not found there either, `None` is returned.
     pub fn get_variable(&mut self, name: &str) -> Option<Value> {
-        {
-            let local_frame = self.current_frame();
-            match local_frame.locals.get(name) {
-                Some(value) => return Some(value.clone()),
-                None => (),
-            }
+        stack::lookup_variable(&self.stack, name).cloned()
+    }
+}
+
+/// Return the byte offset of the given position (as in `MetaToken`) in the
+/// source
+fn byte_offset(source: &str, line_number: u32, column: u32) -> usize {
+    let mut offset = 0;
+    for (i, line) in source.split('\n').enumerate() {
+        if i as u32 + 1 == line_number {
+            return offset + line.char_indices()
+                .nth(column as usize - 1)
+                .map(|(index, _)| index)
+                .unwrap_or(line.len());
+        }
+        // + 1 for the newline
+        offset += line.len() + 1;
+    }
+    source.len()
+}
+
+/// Find the source code of all top-level `LEARN` statements and return a map
+/// from the (uppercase) function name to the source
+fn find_learn_sources(source: &str, tokens: &VecDeque<MetaToken>) -> HashMap<String, String> {
+    use super::lex::Token::{KeyLearn, KeyIf, KeyRepeat, KeyWhile, KeyTry, KeyFor, KeyEnd, Word};
+    let mut result = HashMap::new();
+    let mut depth = 0;
+    // Function name and start offset of the current top-level LEARN
+    let mut current = None;
+    for (i, meta) in tokens.iter().enumerate() {
+        match meta.token {
+            KeyLearn => {
+                if depth == 0 {
+                    if let Some(&MetaToken { token: Word(ref name), .. }) = tokens.get(i + 1) {
+                        let start = byte_offset(source, meta.line_number, meta.column);
+                        current = Some((name.to_uppercase(), start));
+                    }
+                }
+                depth += 1;
+            },
+            KeyIf | KeyRepeat | KeyWhile | KeyTry | KeyFor => depth += 1,
+            KeyEnd => {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some((name, start)) = current.take() {
+                        // END has 3 characters
+                        let end = byte_offset(source, meta.line_number, meta.column + 3);
+                        result.insert(name, source[start..end].to_owned());
+                    }
+                }
+            },
+            _ => (),
         }
-        let global_frame = self.global_frame();
-        global_frame.locals.get(name).cloned()
     }
+    result
 }
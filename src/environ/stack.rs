@@ -41,6 +41,30 @@ impl Default for Frame {
     }
 }
 
+/// The value a local variable had before a loop variable with the same name
+/// was bound, see `Frame::save_local`
+#[derive(Debug)]
+pub struct SavedLocal(String, Option<Value>);
+
+impl Frame {
+    /// Remember the current value of the local variable `name`, so that it can
+    /// be restored with `restore_local` once a loop variable of the same name
+    /// goes out of scope
+    pub fn save_local(&self, name: &str) -> SavedLocal {
+        SavedLocal(name.to_owned(), self.locals.get(name).cloned())
+    }
+
+    /// Restore a local variable saved with `save_local`. If the variable was
+    /// not defined before, it is removed.
+    pub fn restore_local(&mut self, saved: SavedLocal) {
+        let SavedLocal(name, value) = saved;
+        match value {
+            Some(value) => self.locals.insert(name, value),
+            None => self.locals.remove(&name),
+        };
+    }
+}
+
 /// Return a new stack with the root frame (global frame) constructed
 pub fn new_stack() -> Vec<Frame> {
     vec![Frame {
@@ -50,3 +74,53 @@ pub fn new_stack() -> Vec<Frame> {
         .. Frame::default()
     }]
 }
+
+/// Look up the variable `name`, first in the local variables of the innermost
+/// frame and then in the global frame. Variables of the frames in between,
+/// i.e. of calling functions, are not visible.
+pub fn lookup_variable<'a>(stack: &'a [Frame], name: &str) -> Option<&'a Value> {
+    stack.last().and_then(|frame| frame.locals.get(name))
+        .or_else(|| stack.first().and_then(|frame| frame.locals.get(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup_variable, Frame};
+    use super::super::value::Value;
+
+    fn frame_with(name: &str, value: f32) -> Frame {
+        let mut frame = Frame::default();
+        frame.locals.insert(name.to_owned(), Value::Number(value));
+        frame
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let stack = vec![frame_with("size", 50.), frame_with("size", 10.)];
+        assert_eq!(lookup_variable(&stack, "size"), Some(&Value::Number(10.)));
+        assert_eq!(lookup_variable(&stack[..1], "size"), Some(&Value::Number(50.)));
+        // Globals are visible in functions, the caller's locals are not
+        let stack = vec![frame_with("global", 1.), frame_with("caller", 2.), Frame::default()];
+        assert_eq!(lookup_variable(&stack, "global"), Some(&Value::Number(1.)));
+        assert_eq!(lookup_variable(&stack, "caller"), None);
+    }
+
+    #[test]
+    fn test_restore_local() {
+        let mut frame = Frame::default();
+        frame.locals.insert("i".to_owned(), Value::Number(5.));
+        let saved = frame.save_local("i");
+        frame.locals.insert("i".to_owned(), Value::Number(2.));
+        frame.restore_local(saved);
+        assert_eq!(frame.locals.get("i"), Some(&Value::Number(5.)));
+    }
+
+    #[test]
+    fn test_restore_undefined_local() {
+        let mut frame = Frame::default();
+        let saved = frame.save_local("i");
+        frame.locals.insert("i".to_owned(), Value::Number(2.));
+        frame.restore_local(saved);
+        assert_eq!(frame.locals.get("i"), None);
+    }
+}
@@ -17,6 +17,14 @@
 //! everything that doesn't explicitely return something else.
 use std::ops;
 use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Like `try!`, but for `Option`s
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(x) => x, None => return None })
+}
+
 /// Enum combining the possible Rurtle value types
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Value {
@@ -51,6 +59,176 @@ impl Value {
             Value::Nothing => "nothing",
         }
     }
+
+    /// Serialize the value to JSON. Numbers become JSON numbers (or `null` if
+    /// they are not finite), strings become JSON strings, lists become arrays
+    /// and `Nothing` becomes `null`.
+    pub fn to_json(&self) -> String {
+        let mut result = String::new();
+        self.write_json(&mut result);
+        result
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match *self {
+            Value::Number(x) if x.is_finite() => out.push_str(&x.to_string()),
+            Value::Number(_) | Value::Nothing => out.push_str("null"),
+            Value::String(ref s) => {
+                out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            },
+            Value::List(ref l) => {
+                out.push('[');
+                for (i, value) in l.iter().enumerate() {
+                    if i > 0 { out.push(',') };
+                    value.write_json(out);
+                }
+                out.push(']');
+            },
+        }
+    }
+
+    /// Parse a value from JSON, the counterpart to `to_json`. `true` and
+    /// `false` become the numbers 1 and 0. JSON objects have no Rurtle
+    /// equivalent, so they (like any invalid JSON) make this return `None`.
+    pub fn from_json(json: &str) -> Option<Value> {
+        let mut parser = JsonParser { chars: json.chars().peekable() };
+        let value = parser.parse_value();
+        parser.skip_whitespace();
+        if parser.chars.peek().is_some() {
+            return None;
+        }
+        value
+    }
+}
+
+/// A small recursive descent parser for the JSON subset used by
+/// `Value::from_json`
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() { break };
+            self.chars.next();
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Option<()> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn parse_value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('n') => self.expect_word("null").map(|_| Value::Nothing),
+            Some('t') => self.expect_word("true").map(|_| Value::Number(1.)),
+            Some('f') => self.expect_word("false").map(|_| Value::Number(0.)),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') => self.parse_list(),
+            Some(c) if c == '-' || c.is_digit(10) => self.parse_number(),
+            _ => None,
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<Value> {
+        let mut number = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '0'...'9' | '-' | '+' | '.' | 'e' | 'E' => number.push(c),
+                _ => break,
+            }
+            self.chars.next();
+        }
+        number.parse().ok().map(Value::Number)
+    }
+
+    fn parse_hex_escape(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = try_opt!(self.chars.next().and_then(|c| c.to_digit(16)));
+            code = code * 16 + digit;
+        }
+        Some(code)
+    }
+
+    fn parse_string(&mut self) -> Option<String> {
+        // Skip the opening quote
+        self.chars.next();
+        let mut result = String::new();
+        loop {
+            match try_opt!(self.chars.next()) {
+                '"' => return Some(result),
+                '\\' => {
+                    let c = match try_opt!(self.chars.next()) {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = try_opt!(self.parse_hex_escape());
+                            // Characters outside of the BMP are given as a
+                            // surrogate pair
+                            if code >= 0xD800 && code < 0xDC00 {
+                                try_opt!(self.expect_word("\\u"));
+                                let low = try_opt!(self.parse_hex_escape());
+                                if low < 0xDC00 || low >= 0xE000 {
+                                    return None;
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            try_opt!(::std::char::from_u32(code))
+                        },
+                        _ => return None,
+                    };
+                    result.push(c);
+                },
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn parse_list(&mut self) -> Option<Value> {
+        // Skip the opening bracket
+        self.chars.next();
+        let mut result = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Some(Value::List(result));
+        }
+        loop {
+            result.push(try_opt!(self.parse_value()));
+            self.skip_whitespace();
+            match try_opt!(self.chars.next()) {
+                ',' => continue,
+                ']' => return Some(Value::List(result)),
+                _ => return None,
+            }
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -120,9 +298,13 @@ impl<'a> ops::Add for &'a Value {
 
 impl<'a> ops::Sub for &'a Value {
     type Output = Option<Value>;
-    /// Subtracts the second value from the first. This is currently only
-    /// meaningful for a `Number` pair. Every other combination will return
-    /// `None`
+    /// Subtracts the second value from the first. Currently the following
+    /// operations are supported:
+    ///
+    /// * Number - Number = Number: normal subtraction
+    /// * String - String = String: remove every occurence of the second string
+    ///
+    /// Every other combination will return `None`
     fn sub(self, other: &Value) -> Option<Value> {
         match *self {
             Value::Number(a) => {
@@ -132,6 +314,13 @@ impl<'a> ops::Sub for &'a Value {
                 }
             },
 
+            Value::String(ref a) => {
+                match *other {
+                    Value::String(ref b) => Some(Value::String(a.replace(b.as_str(), ""))),
+                    _ => None,
+                }
+            },
+
             _ => None,
         }
     }
@@ -202,3 +391,54 @@ impl<'a> ops::Div for &'a Value {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Value;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let value = Value::List(vec![
+            Value::Number(1.5),
+            string("quote \" backslash \\ newline \n tab \t bell \u{7}"),
+            Value::List(vec![Value::List(vec![]), Value::Nothing, Value::Number(-3.)]),
+        ]);
+        let json = value.to_json();
+        assert_eq!(json, concat!(r#"[1.5,"quote \" backslash \\ newline \n tab \t bell \u0007","#,
+                                 r#"[[],null,-3]]"#));
+        assert_eq!(Value::from_json(&json), Some(value));
+    }
+
+    #[test]
+    fn test_from_json() {
+        assert_eq!(Value::from_json(" [ true , false, 1e2 ] "),
+                   Some(Value::List(vec![Value::Number(1.), Value::Number(0.),
+                                         Value::Number(100.)])));
+        assert_eq!(Value::from_json(r#""ä\/""#), Some(string("ä/")));
+        // Characters outside of the BMP as surrogate pair
+        assert_eq!(Value::from_json(r#""\ud83d\ude00""#), Some(string("\u{1F600}")));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        assert_eq!(Value::from_json("-"), None);
+        assert_eq!(Value::from_json("[1, 2] 3"), None);
+        assert_eq!(Value::from_json("[1, 2"), None);
+        assert_eq!(Value::from_json(r#""\ud83d""#), None);
+        assert_eq!(Value::from_json(r#""\ud83dA""#), None);
+        assert_eq!(Value::from_json(r#"{"a": 1}"#), None);
+        assert_eq!(Value::from_json(""), None);
+    }
+
+    #[test]
+    fn test_string_sub() {
+        assert_eq!(&string("banana") - &string("an"), Some(string("ba")));
+        assert_eq!(&string("banana") - &string("x"), Some(string("banana")));
+        assert_eq!(&string("banana") - &string(""), Some(string("banana")));
+        assert_eq!(&string("banana") - &Value::Number(1.), None);
+    }
+}
@@ -47,13 +47,34 @@ impl BitMap2d {
     }
 }
 
+/// Returns true if every channel of the two colors differs by at most
+/// `tolerance`
+#[inline]
+fn colors_match(a: [u8; 4], b: [u8; 4], tolerance: u8) -> bool {
+    a.iter().zip(b.iter()).all(|(&x, &y)| {
+        let difference = if x > y { x - y } else { y - x };
+        difference <= tolerance
+    })
+}
+
 /// Floodfill the given image, starting at the given `source` point and coloring
 /// everything to `color`. Returns a Patch that contains the given colorized blob
 /// with a transparent background. Also returns the (x, y) coordinates of the
 /// upper left corner of the patch rectangle.
-pub fn floodfill(img: &image::DynamicImage, start: (u32, u32), color: (u8, u8, u8, u8))
-                 -> (u32, u32, image::DynamicImage)
+///
+/// A pixel is filled if none of its channels differ by more than `tolerance`
+/// from the pixel at the starting point. A tolerance of 0 only fills pixels
+/// with exactly the same color.
+///
+/// `interrupted` is called regularly during the fill. If it returns true, the
+/// fill is aborted and `None` is returned.
+pub fn floodfill<F>(img: &image::DynamicImage, start: (u32, u32), color: (u8, u8, u8, u8),
+                    tolerance: u8, mut interrupted: F)
+                    -> Option<(u32, u32, image::DynamicImage)>
+    where F: FnMut() -> bool
 {
+    // How many pixels are handled before checking for an interruption
+    const CHECK_INTERVAL: usize = 16384;
     let (width, height) = img.dimensions();
     let mut result = Vec::new();
     let mut visited = BitMap2d::new(width, height);
@@ -62,9 +83,14 @@ pub fn floodfill(img: &image::DynamicImage, start: (u32, u32), color: (u8, u8, u
     let target_color = [color.0, color.1, color.2, color.3];
     queue.push(start);
     let mut neighbors = Vec::with_capacity(4);
+    let mut steps = 0;
     while let Some(point) = queue.pop() {
+        steps += 1;
+        if steps % CHECK_INTERVAL == 0 && interrupted() {
+            return None;
+        }
         let (x, y) = point;
-        if source_color != img.get_pixel(x, y).data { continue }
+        if !colors_match(source_color, img.get_pixel(x, y).data, tolerance) { continue }
         // Checking only 4 neighbors
         neighbors.clear();
         if x < width - 1 { neighbors.push((x+1, y)) };
@@ -85,7 +111,7 @@ pub fn floodfill(img: &image::DynamicImage, start: (u32, u32), color: (u8, u8, u
     for (x, y) in result {
         image.put_pixel(x - min_x, y - min_y, image::Rgba { data: target_color } );
     }
-    (min_x, min_y, image)
+    Some((min_x, min_y, image))
 }
 
 /// Takes a list of (x, y) coordinates and returns (min_x, max_x, min_y, max_y)
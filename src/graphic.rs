@@ -7,8 +7,12 @@
 //! Unlike in other graphic libraries, the origin (0, 0) is in the middle of the
 //! whole canvas. Positive x/y coordinates go right/up and negative ones
 //! left/down. The canvas does have as many pixels as the window size, thus it
-//! is possible to increase the section shown by resizing the window. Scrolling
-//! and zooming are currently not supported.
+//! is possible to increase the section shown by resizing the window.
+//!
+//! The view can also be zoomed and panned, either with `set_zoom` and `pan_by`
+//! or interactively with the mouse wheel and the arrow keys (see
+//! `handle_events`). Zooming and panning only change what is shown, the turtle
+//! coordinates of the shapes stay the same.
 //!
 //! # Drawing and events
 //!
@@ -30,7 +34,13 @@ use image::{self, GenericImage};
 use glium::{self, Surface};
 use glium_text;
 use na;
-use std::io;
+use std::{fs, io, thread, time};
+use std::collections::HashSet;
+use std::f32::INFINITY;
+use std::mem;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
 use super::floodfill as ff;
 
 /// A Point to pass around to shaders.
@@ -64,6 +74,28 @@ const FONT_DATA: &'static [u8] = include_bytes!("dejavusansmono.ttf");
 
 type ScaleMatrix = [[f32; 4]; 4];
 
+/// Factor by which the zoom changes for each "line" scrolled with the mouse
+/// wheel
+const ZOOM_STEP: f32 = 1.1;
+/// Smallest and biggest zoom that can be reached with the mouse wheel
+const MIN_WHEEL_ZOOM: f32 = 0.01;
+const MAX_WHEEL_ZOOM: f32 = 100.;
+/// Amount of pixels the view is moved when an arrow key is pressed
+const PAN_STEP: f32 = 20.;
+/// Font size that is used for texts if no size is given
+pub const DEFAULT_FONT_SIZE: f32 = 12.;
+/// Length of a single dash (and the gap after it) in dashed lines
+const DASH_LENGTH: f32 = 8.;
+/// Length of a single dot in dotted lines
+const DOT_LENGTH: f32 = 1.;
+/// Length of the gap after a dot in dotted lines
+const DOT_GAP: f32 = 4.;
+/// Minimum distance (in pixels) between two gridlines, if they'd be closer only
+/// the axes are drawn
+const MIN_GRID_GAP: f32 = 4.;
+/// Number of vertices the line vertex buffer has room for at least
+const MIN_LINE_BUFFER: usize = 1024;
+
 /// Module for color aliases
 pub mod color {
     /// Alias for a 4-f32 tuple, representing the colors as RGB values and the alpha
@@ -74,6 +106,12 @@ pub mod color {
     pub const RED: Color = (1.0, 0.0, 0.0, 1.0);
     pub const GREEN: Color = (0.0, 1.0, 0.0, 1.0);
     pub const BLUE: Color = (0.0, 0.0, 1.0, 1.0);
+    /// Color in which traced segments are highlighted
+    pub const TRACE: Color = (1.0, 0.6, 0.0, 1.0);
+    /// Color of the gridlines
+    pub const GRID: Color = (0.85, 0.85, 0.85, 1.0);
+    /// Color of the x and y axes in the grid
+    pub const AXES: Color = (0.4, 0.4, 0.4, 1.0);
 
     /// Convert a Color (4-tuple of f32) to a color array ([f32; 4]). Useful for
     /// sending it to shaders.
@@ -83,12 +121,62 @@ pub mod color {
     }
 }
 
-/// A Line is defined via startpoint, endpoint and a color
-struct Line(f32, f32, f32, f32, color::Color);
-/// A Text is defined via anchor point, angle, color and text
-struct Text(f32, f32, f32, color::Color, String);
-/// A filled area is defined via a patch texture and a starting point
-struct Fill(f32, f32, glium::texture::Texture2d);
+/// The style in which a line is drawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineStyle {
+    /// A continuous line
+    Solid,
+    /// A line made of dashes
+    Dashed,
+    /// A line made of dots
+    Dotted,
+}
+
+/// The different kinds of shapes on the screen, used to remove only the shapes
+/// of a certain kind
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShapeKind {
+    Line,
+    Text,
+    Fill,
+    Stamp,
+}
+
+/// The different shapes the turtle can be drawn as
+#[derive(Clone)]
+pub enum TurtleShape {
+    /// The default shape, Ferris the crab
+    Ferris,
+    /// A simple isosceles triangle pointing in the turtle's direction
+    Triangle,
+    /// The classic arrowhead known from other turtle implementations
+    Classic,
+    /// A custom image, loaded with `TurtleScreen::load_turtle_image`
+    Image(Rc<glium::texture::Texture2d>),
+}
+
+/// A Line is defined via startpoint, endpoint, color and style
+struct Line(f32, f32, f32, f32, color::Color, LineStyle);
+/// Horizontal alignment of a text relative to its anchor point
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alignment {
+    /// The text starts at the anchor point
+    Left,
+    /// The text is centered on the anchor point
+    Center,
+    /// The text ends at the anchor point
+    Right,
+}
+
+/// A Text is defined via anchor point, angle, color, text, font size and
+/// alignment
+struct Text(f32, f32, f32, color::Color, String, f32, Alignment);
+/// A filled area is defined via a starting point, the zoom level at which the
+/// patch was created and a patch texture
+struct Fill(f32, f32, f32, glium::texture::Texture2d);
+/// A stamp is a copy of the turtle, defined via position, orientation, color,
+/// scale and the shape the turtle had
+struct Stamp(f32, f32, f32, color::Color, f32, TurtleShape);
 
 /// Enum for every possible shape object
 // We need this for a Vec<Shape> so that we can store the original order of
@@ -99,10 +187,24 @@ struct Fill(f32, f32, glium::texture::Texture2d);
 //     draw_text(&mut Frame, f32, f32, f32, color::Color, &String)
 // or
 //     draw_text(&mut Frame, &Shape), which would require pattern matching twice
+//
+// Lines additionally store which part of the line vertex buffer they occupy.
 enum Shape {
-    Line(Line),
+    Line(Line, Range<usize>),
     Text(Text),
     Fill(Fill),
+    Stamp(Stamp),
+}
+
+impl Shape {
+    fn kind(&self) -> ShapeKind {
+        match *self {
+            Shape::Line(..) => ShapeKind::Line,
+            Shape::Text(_) => ShapeKind::Text,
+            Shape::Fill(_) => ShapeKind::Fill,
+            Shape::Stamp(_) => ShapeKind::Stamp,
+        }
+    }
 }
 
 /// A `TurtleScreen` is a window that houses a turtle. It provides some graphic
@@ -111,16 +213,52 @@ pub struct TurtleScreen {
     window: glium::backend::glutin_backend::GlutinFacade,
     program: glium::Program,
     shapes: Vec<Shape>,
+    // The vertices of every line, in the order of the lines in `shapes`. They
+    // are kept in a single buffer on the GPU so that they don't have to be
+    // uploaded again for each frame. Only the first `uploaded_vertices`
+    // vertices have been written to the buffer so far.
+    line_vertices: Vec<Point>,
+    line_buffer: Option<glium::VertexBuffer<Point>>,
+    uploaded_vertices: usize,
     _is_closed: bool,
+    paused: bool,
+    screenshot_results: Vec<image::ImageResult<PathBuf>>,
     ferris: glium::texture::Texture2d,
     ferris_program: glium::Program,
     patch_program: glium::Program,
     text_system: glium_text::TextSystem,
     font: glium_text::FontTexture,
+    zoom: f32,
+    pan: (f32, f32),
+    cursor_position: (i32, i32),
+    last_click: Option<(f32, f32)>,
+    pressed_keys: HashSet<glium::glutin::VirtualKeyCode>,
+    recording: Option<Vec<image::DynamicImage>>,
+    canvas_cache: Option<image::DynamicImage>,
+    antialiasing: bool,
+    background_image: Option<(glium::texture::Texture2d, bool)>,
+    grid: Option<f32>,
+    /// If this is set to true, the grid (if shown) is also part of screenshots
+    /// and recordings
+    pub grid_in_screenshots: bool,
+    /// Pressing this key in the window saves a screenshot (see
+    /// `save_screenshot`), `None` disables the shortcut
+    pub screenshot_key: Option<glium::glutin::VirtualKeyCode>,
+    /// The directory in which screenshots taken with `screenshot_key` are
+    /// saved
+    pub screenshot_directory: PathBuf,
+    /// Pressing this key in the window toggles the paused state (see
+    /// `is_paused`), `None` disables the shortcut
+    pub pause_key: Option<glium::glutin::VirtualKeyCode>,
     /// The position of the turtle on the canvas
     pub turtle_position: (f32, f32),
     /// The color of the turtle
     pub turtle_color: color::Color,
+    /// The shape that the turtle is drawn as
+    pub turtle_shape: TurtleShape,
+    /// The factor by which the turtle is scaled when drawn, 1.0 is the
+    /// normal size
+    pub turtle_scale: f32,
     /// The orientation of the turtle in degrees where 0° is north and positive
     /// degrees count counter-clockwise
     pub turtle_orientation: f32,
@@ -128,6 +266,9 @@ pub struct TurtleScreen {
     pub turtle_hidden: bool,
     /// Background color of the turtle screen
     pub background_color: color::Color,
+    /// A segment (start and end point) that is drawn highlighted on top of the
+    /// other shapes, used to show where the turtle is going
+    pub trace_segment: Option<((f32, f32), (f32, f32))>,
 }
 
 impl TurtleScreen {
@@ -171,109 +312,556 @@ impl TurtleScreen {
             window: window,
             program: program,
             shapes: Vec::new(),
+            line_vertices: Vec::new(),
+            line_buffer: None,
+            uploaded_vertices: 0,
             _is_closed: false,
+            paused: false,
+            screenshot_results: Vec::new(),
             ferris: ferris_texture,
             ferris_program: ferris_program,
             patch_program: patch_program,
             text_system: text_system,
             font: font,
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+            cursor_position: (0, 0),
+            last_click: None,
+            pressed_keys: HashSet::new(),
+            recording: None,
+            canvas_cache: None,
+            antialiasing: true,
+            background_image: None,
+            grid: None,
+            grid_in_screenshots: false,
+            screenshot_key: Some(glium::glutin::VirtualKeyCode::F12),
+            screenshot_directory: PathBuf::from("."),
+            pause_key: Some(glium::glutin::VirtualKeyCode::Space),
             turtle_position: (0.0, 0.0),
             turtle_color: color::BLACK,
+            turtle_shape: TurtleShape::Ferris,
+            turtle_scale: 1.0,
             turtle_orientation: 0.0,
             turtle_hidden: false,
             background_color: color::WHITE,
+            trace_segment: None,
         }
     }
 
     /// Add a line to the collection, going from point start to point end
     pub fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: color::Color) {
-        self.shapes.push(Shape::Line(Line(start.0, start.1, end.0, end.1, color)));
+        self.add_styled_line(start, end, color, LineStyle::Solid);
+    }
+
+    /// Add a line with the given style to the collection
+    pub fn add_styled_line(&mut self, start: (f32, f32), end: (f32, f32), color: color::Color,
+                           style: LineStyle) {
+        let line = Line(start.0, start.1, end.0, end.1, color, style);
+        let first = self.line_vertices.len();
+        self.line_vertices.extend(line_points(&line));
+        let range = first..self.line_vertices.len();
+        self.shapes.push(Shape::Line(line, range));
+    }
+
+    /// Add a solid line that is made up of the given consecutive pieces
+    /// `(start, end, color)`, so that each piece can have its own color. The
+    /// pieces count as a single line, e.g. for `shape_count_of`.
+    pub fn add_line_pieces(&mut self, pieces: &[((f32, f32), (f32, f32), color::Color)]) {
+        let (start, end, color) = match (pieces.first(), pieces.last()) {
+            (Some(&(start, _, _)), Some(&(_, end, color))) => (start, end, color),
+            _ => return,
+        };
+        let first = self.line_vertices.len();
+        for &(from, to, color) in pieces {
+            let piece = Line(from.0, from.1, to.0, to.1, color, LineStyle::Solid);
+            self.line_vertices.extend(line_points(&piece));
+        }
+        let range = first..self.line_vertices.len();
+        let line = Line(start.0, start.1, end.0, end.1, color, LineStyle::Solid);
+        self.shapes.push(Shape::Line(line, range));
     }
 
     /// Add a new text to the screen
     pub fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: color::Color, text: &str) {
-        self.shapes.push(Shape::Text(Text(anchor.0, anchor.1, angle, color, text.to_owned())));
+        self.add_text_ex(anchor, angle, color, text, DEFAULT_FONT_SIZE, Alignment::Left);
+    }
+
+    /// Add a new text with the given font size and alignment to the screen
+    pub fn add_text_ex(&mut self, anchor: (f32, f32), angle: f32, color: color::Color, text: &str,
+                       size: f32, alignment: Alignment) {
+        self.shapes.push(Shape::Text(Text(anchor.0, anchor.1, angle, color, text.to_owned(),
+                                          size, alignment)));
     }
 
-    /// Floodfill the image at the given point with the given color
-    pub fn floodfill(&mut self, point: (f32, f32), color: color::Color) {
+    /// Add a copy of the turtle at the given position and orientation that
+    /// stays on the screen. The stamp uses the current turtle color, scale and
+    /// shape.
+    pub fn add_stamp(&mut self, position: (f32, f32), orientation: f32) {
+        let stamp = Stamp(position.0, position.1, orientation, self.turtle_color,
+                          self.turtle_scale, self.turtle_shape.clone());
+        self.shapes.push(Shape::Stamp(stamp));
+    }
+
+    /// Floodfill the image at the given point with the given color. The
+    /// tolerance is given in the range [0; 1] and specifies how much the color
+    /// of a pixel may differ from the color at the starting point to still be
+    /// filled (see `floodfill::floodfill`).
+    ///
+    /// Returns false and doesn't fill anything if the point is outside of the
+    /// visible canvas.
+    ///
+    /// While filling, the window's events are still handled. If the window is
+    /// closed during that time, the fill is aborted and false is returned as
+    /// well.
+    pub fn floodfill(&mut self, point: (f32, f32), color: color::Color, tolerance: f32) -> bool {
         // we floodfill with the turtle not shown
-        let original_state = self.turtle_hidden;
-        self.turtle_hidden = true;
-        self.draw_and_update();
-        let image = self.screenshot();
-        self.turtle_hidden = original_state;
-        self.draw_and_update();
+        let image = self.canvas_image();
         // point is given in turtle coordinates with (0,0) being in the middle, we
         // need to translate it to picture coordinates
         let (width, height) = image.dimensions();
-        let (adj_x, adj_y) = ((width as f32 / 2. + point.0) as u32,
-                              // minus here because the image coordinates have the
-                              // y-axis downwards while turtle coordinates have the
-                              // y-axis upwars
-                              (height as f32 / 2. - point.1) as u32);
+        let (adj_x, adj_y) = self.to_image_coordinates(point, (width, height));
+        if adj_x < 0. || adj_y < 0. || adj_x >= width as f32 || adj_y >= height as f32 {
+            // Show the turtle again
+            self.draw_and_update();
+            return false;
+        }
+        let (adj_x, adj_y) = (adj_x as u32, adj_y as u32);
+        const MAX: f32 = ::std::u8::MAX as f32;
         let translated_color = {
             let (r, g, b, a) = color;
-            const MAX: f32 = ::std::u8::MAX as f32;
             ((MAX * r) as u8, (MAX * g) as u8, (MAX * b) as u8, (MAX * a) as u8)
         };
-        let (px, py, patch) = ff::floodfill(&image, (adj_x, adj_y), translated_color);
+        let tolerance = (MAX * tolerance.max(0.).min(1.)) as u8;
+        // Handling the events may change the view while we're filling, so we
+        // remember where the image's origin was
+        let zoom = self.zoom;
+        let origin = self.to_turtle_coordinates((0., 0.), (width, height));
+        let result = ff::floodfill(&image, (adj_x, adj_y), translated_color, tolerance, || {
+            self.handle_events();
+            self.is_closed()
+        });
+        let (px, py, patch) = match result {
+            Some(fill) => fill,
+            None => return false,
+        };
         // We need to translate back the start coordinates
-        let (trans_x, trans_y) = (px as f32 - width as f32 / 2.,
-                                  height as f32 / 2. - py as f32);
+        let (trans_x, trans_y) = (origin.0 + px as f32 / zoom, origin.1 - py as f32 / zoom);
         self.shapes.push(Shape::Fill(
-            Fill(trans_x, trans_y,
+            Fill(trans_x, trans_y, zoom,
                  image_to_texture(&self.window, patch).expect("Conversion to texture failed"))));
+        self.draw_and_update();
+        true
+    }
+
+    /// Return the color of the canvas at the given point (in turtle
+    /// coordinates), or `None` if the point is outside of the visible canvas.
+    /// Neither the turtle nor the grid are taken into account.
+    ///
+    /// Reading the canvas is expensive, so it is only done once until the
+    /// screen is drawn again.
+    pub fn get_pixel(&mut self, point: (f32, f32)) -> Option<color::Color> {
+        if self.canvas_cache.is_none() {
+            let image = self.canvas_image();
+            self.canvas_cache = Some(image);
+        }
+        let image = self.canvas_cache.as_ref().unwrap();
+        let (width, height) = image.dimensions();
+        let (x, y) = self.to_image_coordinates(point, (width, height));
+        if x < 0. || y < 0. || x >= width as f32 || y >= height as f32 {
+            return None;
+        }
+        const MAX: f32 = ::std::u8::MAX as f32;
+        let data = image.get_pixel(x as u32, y as u32).data;
+        Some((data[0] as f32 / MAX, data[1] as f32 / MAX, data[2] as f32 / MAX,
+              data[3] as f32 / MAX))
+    }
+
+    /// Render the canvas without the turtle and the grid and return it as image.
+    /// The canvas is rendered offscreen, so the window and a recording are not
+    /// affected.
+    fn canvas_image(&mut self) -> image::DynamicImage {
+        let grid = self.grid.take();
+        let original_state = self.turtle_hidden;
+        self.turtle_hidden = true;
+        let image = self.render_to_image();
+        self.turtle_hidden = original_state;
+        self.grid = grid;
+        image
+    }
+
+    /// Translate a point given in turtle coordinates to the pixel coordinates of
+    /// an image (such as a screenshot) with the given dimensions, taking the
+    /// current zoom and pan into account.
+    fn to_image_coordinates(&self, point: (f32, f32), dimensions: (u32, u32)) -> (f32, f32) {
+        let (width, height) = dimensions;
+        ((point.0 - self.pan.0) * self.zoom + width as f32 / 2.,
+         // minus here because the image coordinates have the y-axis downwards
+         // while turtle coordinates have the y-axis upwards
+         height as f32 / 2. - (point.1 - self.pan.1) * self.zoom)
+    }
+
+    /// The inverse of `to_image_coordinates`
+    fn to_turtle_coordinates(&self, point: (f32, f32), dimensions: (u32, u32)) -> (f32, f32) {
+        let (width, height) = dimensions;
+        ((point.0 - width as f32 / 2.) / self.zoom + self.pan.0,
+         (height as f32 / 2. - point.1) / self.zoom + self.pan.1)
+    }
+
+    /// Set the zoom factor. A zoom of 1 shows the canvas in its original size,
+    /// greater values zoom in and smaller values zoom out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `zoom` is not a positive number.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        assert!(zoom > 0.0, "zoom has to be positive");
+        self.zoom = zoom;
+        self.canvas_cache = None;
+    }
+
+    /// Enable or disable smoothing of the lines. Antialiasing is enabled by
+    /// default.
+    pub fn set_antialiasing(&mut self, antialiasing: bool) {
+        self.antialiasing = antialiasing;
+    }
+
+    /// Return the size (width, height) of the canvas in pixels
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.window.get_framebuffer_dimensions()
+    }
+
+    /// Return the current zoom factor
+    pub fn get_zoom(&self) -> f32 { self.zoom }
+
+    /// Move the view by the given amount (in turtle coordinates). A positive
+    /// `dx` moves the view to the right, thus the drawing appears to move left.
+    pub fn pan_by(&mut self, dx: f32, dy: f32) {
+        self.pan = (self.pan.0 + dx, self.pan.1 + dy);
+        self.canvas_cache = None;
+    }
+
+    /// Return the point (in turtle coordinates) that is shown in the center of
+    /// the screen
+    pub fn get_pan(&self) -> (f32, f32) { self.pan }
+
+    /// Set zoom and pan so that the whole drawing (see `bounding_box`) fills
+    /// the window, with a small margin. If nothing has been drawn, the view is
+    /// reset to the origin with zoom 1.
+    pub fn fit_to_drawing(&mut self) {
+        // Margin in pixels around the drawing
+        const MARGIN: f32 = 20.;
+        let (min_x, min_y, max_x, max_y) = self.bounding_box();
+        let (width, height) = self.get_dimensions();
+        let available = ((width as f32 - 2. * MARGIN).max(1.),
+                         (height as f32 - 2. * MARGIN).max(1.));
+        let (drawing_width, drawing_height) = (max_x - min_x, max_y - min_y);
+        // A drawing that is only a line or point has no extent in one or both
+        // directions, it then doesn't limit the zoom
+        let zoom_x = if drawing_width > 0. { available.0 / drawing_width } else { INFINITY };
+        let zoom_y = if drawing_height > 0. { available.1 / drawing_height } else { INFINITY };
+        let zoom = zoom_x.min(zoom_y);
+        self.set_zoom(if zoom.is_finite() && zoom > 0. { zoom } else { 1.0 });
+        self.pan = ((min_x + max_x) / 2., (min_y + max_y) / 2.);
+    }
+
+    /// Show a grid with the given spacing (in turtle units) and the x and y
+    /// axes through the origin behind the drawing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spacing` is not a positive number.
+    pub fn show_grid(&mut self, spacing: f32) {
+        assert!(spacing > 0.0, "grid spacing has to be positive");
+        self.grid = Some(spacing);
+    }
+
+    /// Hide the grid again
+    pub fn hide_grid(&mut self) {
+        self.grid = None;
+    }
+
+    /// Load the TrueType font from the given file and use it for all texts.
+    /// Texts that are already on the screen are drawn with the new font too.
+    pub fn set_font(&mut self, path: &str) -> io::Result<()> {
+        let file = try!(fs::File::open(path));
+        match glium_text::FontTexture::new(&self.window, file, 24) {
+            Ok(font) => {
+                self.font = font;
+                Ok(())
+            },
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                         format!("{} is not a valid font", path))),
+        }
+    }
+
+    /// Load the image from the given file and show it behind the drawing. The
+    /// image is centered on the origin and stretched to the size of the
+    /// canvas, if `keep_aspect` is true it is only scaled so that it fits
+    /// into the canvas. Like the drawing, it moves when zooming and panning.
+    pub fn set_background_image(&mut self, path: &str, keep_aspect: bool)
+                                -> image::ImageResult<()> {
+        let image = try!(image::open(path));
+        let texture = try!(image_to_texture(&self.window, image).map_err(texture_error));
+        self.background_image = Some((texture, keep_aspect));
+        Ok(())
+    }
+
+    /// Load the image at the given path so that it can be used as the
+    /// turtle's shape
+    pub fn load_turtle_image(&self, path: &str) -> image::ImageResult<TurtleShape> {
+        let image = try!(image::open(path));
+        let texture = try!(image_to_texture(&self.window, image).map_err(texture_error));
+        Ok(TurtleShape::Image(Rc::new(texture)))
+    }
+
+    /// Remove the background image again
+    pub fn clear_background_image(&mut self) {
+        self.background_image = None;
+    }
+
+    /// Return the number of shapes (lines, texts, fills and stamps) on the
+    /// screen
+    pub fn shape_count(&self) -> usize {
+        self.shapes.len()
+    }
+
+    /// Return the number of shapes of the given kind on the screen
+    pub fn shape_count_of(&self, kind: ShapeKind) -> usize {
+        self.shapes.iter().filter(|shape| shape.kind() == kind).count()
+    }
+
+    /// Change the title of the window
+    pub fn set_title(&self, title: &str) {
+        if let Some(window) = self.window.get_window() {
+            window.set_title(title);
+        }
+    }
+
+    /// Return the smallest rectangle (in turtle coordinates) containing every
+    /// shape as `(min_x, min_y, max_x, max_y)`. Lines and filled areas are
+    /// fully taken into account, texts and stamps only with their anchor
+    /// point. Points with infinite or NaN coordinates are skipped. If nothing
+    /// has been drawn, `(0, 0, 0, 0)` is returned.
+    pub fn bounding_box(&self) -> (f32, f32, f32, f32) {
+        use std::f32::NEG_INFINITY;
+        let mut bbox = (INFINITY, INFINITY, NEG_INFINITY, NEG_INFINITY);
+        {
+            let mut include = |x: f32, y: f32| {
+                if !(x.is_finite() && y.is_finite()) {
+                    return;
+                }
+                bbox.0 = bbox.0.min(x);
+                bbox.1 = bbox.1.min(y);
+                bbox.2 = bbox.2.max(x);
+                bbox.3 = bbox.3.max(y);
+            };
+            for shape in &self.shapes {
+                match *shape {
+                    Shape::Line(Line(x1, y1, x2, y2, _, _), _) => {
+                        include(x1, y1);
+                        include(x2, y2);
+                    },
+                    Shape::Text(Text(x, y, _, _, _, _, _)) => include(x, y),
+                    Shape::Fill(Fill(x, y, scale, ref texture)) => {
+                        include(x, y);
+                        include(x + texture.get_width() as f32 / scale,
+                                y - texture.get_height().unwrap() as f32 / scale);
+                    },
+                    Shape::Stamp(Stamp(x, y, _, _, _, _)) => include(x, y),
+                }
+            }
+        }
+        if bbox.0 > bbox.2 {
+            // No (finite) point was included
+            (0., 0., 0., 0.)
+        } else {
+            bbox
+        }
+    }
+
+    /// Remove every shape except for the first `len` ones, i.e. remove the
+    /// shapes that have been added last
+    pub fn truncate_shapes(&mut self, len: usize) {
+        self.shapes.truncate(len);
+        self.rebuild_line_vertices();
     }
 
     /// Remove all drawn lines. Note that this does not change the turtle's
     /// position, color or orientation.
     pub fn clear(&mut self) {
         self.shapes.clear();
+        self.rebuild_line_vertices();
+    }
+
+    /// Remove only the shapes of the given kind, e.g. all texts
+    pub fn clear_kind(&mut self, kind: ShapeKind) {
+        self.shapes.retain(|shape| shape.kind() != kind);
+        self.rebuild_line_vertices();
+    }
+
+    /// Collect the vertices of the remaining lines after shapes have been
+    /// removed. The buffer is written again on the next redraw.
+    fn rebuild_line_vertices(&mut self) {
+        let old_vertices = mem::replace(&mut self.line_vertices, Vec::new());
+        for shape in &mut self.shapes {
+            if let Shape::Line(_, ref mut range) = *shape {
+                let first = self.line_vertices.len();
+                self.line_vertices.extend_from_slice(&old_vertices[range.clone()]);
+                *range = first..self.line_vertices.len();
+            }
+        }
+        self.uploaded_vertices = 0;
+    }
+
+    /// Write the vertices that are not yet in the line buffer to it. If the
+    /// buffer is too small, a bigger one is created.
+    fn update_line_buffer(&mut self) {
+        let len = self.line_vertices.len();
+        let capacity = self.line_buffer.as_ref().map(|buffer| buffer.len()).unwrap_or(0);
+        if len > capacity {
+            // Leave room for more lines, so that the buffer isn't created
+            // again for every new line
+            let capacity = ::std::cmp::max(2 * len, MIN_LINE_BUFFER);
+            self.line_buffer = Some(glium::VertexBuffer::empty_dynamic(&self.window, capacity)
+                                    .expect("Creating the line buffer failed"));
+            self.uploaded_vertices = 0;
+        }
+        if self.uploaded_vertices < len {
+            if let Some(ref buffer) = self.line_buffer {
+                buffer.slice(self.uploaded_vertices..len).unwrap()
+                    .write(&self.line_vertices[self.uploaded_vertices..]);
+            }
+            self.uploaded_vertices = len;
+        }
+    }
+
+    /// Start recording. Until `stop_recording` is called, every call to
+    /// `draw_and_update` saves the drawn frame. Calling this function while a
+    /// recording is in progress discards the frames recorded so far.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Stop recording and return the recorded frames in order. Returns an
+    /// empty vector if no recording was in progress.
+    pub fn stop_recording(&mut self) -> Vec<image::DynamicImage> {
+        self.recording.take().unwrap_or_else(Vec::new)
     }
 
-    /// Draw everything and update the screen
-    pub fn draw_and_update(&self) {
+    /// Draw everything and update the screen. If a recording is in progress,
+    /// the frame is also saved.
+    pub fn draw_and_update(&mut self) {
+        self.canvas_cache = None;
+        self.update_line_buffer();
         let mut frame = self.window.draw();
+        self.render(&mut frame);
+        frame.finish().unwrap();
+        if self.recording.is_some() {
+            let shot = self.screenshot();
+            if let Some(ref mut frames) = self.recording {
+                frames.push(shot);
+            }
+        }
+    }
+
+    /// Draw everything onto the given surface. The line buffer has to be up to
+    /// date, see `update_line_buffer`.
+    fn render<S: Surface>(&self, frame: &mut S) {
         {
             let (br, bg, bb, ba) = self.background_color;
             frame.clear_color(br, bg, bb, ba);
         }
         let (width, height) = frame.get_dimensions();
+        let (scale_x, scale_y) = (2.0 * self.zoom / width as f32, 2.0 * self.zoom / height as f32);
         let matrix = [
-            [2.0 / width as f32, 0.0, 0.0, 0.0],
-            [0.0, 2.0 / height as f32, 0.0, 0.0],
+            [scale_x, 0.0, 0.0, 0.0],
+            [0.0, scale_y, 0.0, 0.0],
             [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
+            [-self.pan.0 * scale_x, -self.pan.1 * scale_y, 0.0, 1.0],
         ];
+        if let Some((ref texture, keep_aspect)) = self.background_image {
+            self.draw_background_image(frame, texture, keep_aspect, matrix);
+        }
+        if let Some(spacing) = self.grid {
+            self.draw_grid(frame, spacing, matrix);
+        }
+        // Consecutive lines are drawn with a single call
+        let mut pending_lines: Option<Range<usize>> = None;
         for shape in &self.shapes {
+            if let Shape::Line(_, ref range) = *shape {
+                pending_lines = Some(match pending_lines {
+                    Some(pending) => pending.start..range.end,
+                    None => range.clone(),
+                });
+                continue;
+            }
+            if let Some(range) = pending_lines.take() {
+                self.draw_lines(frame, range, matrix);
+            }
             match *shape {
-                Shape::Line(ref l) => self.draw_line(&mut frame, l, matrix),
-                Shape::Text(ref t) => self.draw_text(&mut frame, t),
-                Shape::Fill(ref f) => self.draw_fill(&mut frame, f, matrix),
+                Shape::Line(..) => unreachable!(),
+                Shape::Text(ref t) => self.draw_text(frame, t),
+                Shape::Fill(ref f) => self.draw_fill(frame, f, matrix),
+                Shape::Stamp(Stamp(x, y, orientation, color, scale, ref shape)) =>
+                    self.draw_turtle_shape(frame, shape, (x, y), orientation, color,
+                                           scale, matrix),
             }
         }
+        if let Some(range) = pending_lines {
+            self.draw_lines(frame, range, matrix);
+        }
+        if let Some((start, end)) = self.trace_segment {
+            let line = Line(start.0, start.1, end.0, end.1, color::TRACE, LineStyle::Solid);
+            self.draw_line(frame, &line, matrix);
+        }
         if !self.turtle_hidden {
-            self.draw_turtle(&mut frame, matrix);
+            self.draw_turtle(frame, matrix);
         }
-        frame.finish().unwrap();
     }
 
-    fn draw_fill(&self, frame: &mut glium::Frame, fill: &Fill, matrix: ScaleMatrix) {
-        let Fill(x, y, ref texture) = *fill;
-        let (width, height) = (texture.get_width() as f32,
-                               texture.get_height().unwrap() as f32);
+    /// Render everything into an offscreen RGBA texture and return it as image.
+    /// Unlike the window's front buffer, the texture always has an alpha
+    /// channel, so a transparent background stays transparent.
+    fn render_to_image(&mut self) -> image::DynamicImage {
+        use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+        self.update_line_buffer();
+        let (width, height) = self.window.get_framebuffer_dimensions();
+        let texture = glium::texture::Texture2d::empty_with_format(
+            &self.window, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap,
+            width, height).unwrap();
+        {
+            let mut target = glium::framebuffer::SimpleFrameBuffer::new(&self.window, &texture)
+                .unwrap();
+            self.render(&mut target);
+        }
+        let raw: glium::texture::RawImage2d<u8> = texture.read();
+        raw_image_to_image(raw)
+    }
+
+    fn draw_background_image<S: Surface>(&self, frame: &mut S,
+                                         texture: &glium::texture::Texture2d,
+                                         keep_aspect: bool, matrix: ScaleMatrix) {
+        let (width, height) = frame.get_dimensions();
+        let (mut width, mut height) = (width as f32, height as f32);
+        if keep_aspect {
+            let (image_width, image_height) = (texture.get_width() as f32,
+                                               texture.get_height().unwrap() as f32);
+            let scale = (width / image_width).min(height / image_height);
+            width = image_width * scale;
+            height = image_height * scale;
+        }
+        let (dx, dy) = (width / 2., height / 2.);
         let vertex_buffer = glium::VertexBuffer::new(
             &self.window,
             &vec![
                 // Bottom left corner
-                FerrisPoint { coords: [x, y - height], tex_coords: [0., 0.] },
+                FerrisPoint { coords: [-dx, -dy], tex_coords: [0., 0.] },
                 // Bottom right corner
-                FerrisPoint { coords: [x + width, y - height], tex_coords: [1., 0.] },
+                FerrisPoint { coords: [dx, -dy], tex_coords: [1., 0.] },
                 // Top right corner
-                FerrisPoint { coords: [x + width, y], tex_coords: [1., 1.] },
+                FerrisPoint { coords: [dx, dy], tex_coords: [1., 1.] },
                 // Top left corner
-                FerrisPoint { coords: [x, y], tex_coords: [0., 1.] },
+                FerrisPoint { coords: [-dx, dy], tex_coords: [0., 1.] },
         ]);
         let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
         let uniforms = uniform! {
@@ -284,13 +872,37 @@ impl TurtleScreen {
                    &Default::default()).unwrap();
     }
 
-    fn draw_line(&self, frame: &mut glium::Frame, line: &Line, matrix: ScaleMatrix) {
+    fn draw_grid<S: Surface>(&self, frame: &mut S, spacing: f32, matrix: ScaleMatrix) {
         use std::default::Default;
         use self::color::to_array;
-        let mut points: Vec<Point> = Vec::new();
-        let Line(x1, y1, x2, y2, color) = *line;
-        points.push(Point { coords: [x1, y1], color: to_array(color) });
-        points.push(Point { coords: [x2, y2], color: to_array(color) });
+        let (width, height) = frame.get_dimensions();
+        // The visible area in turtle coordinates
+        let (half_width, half_height) = (width as f32 / 2. / self.zoom,
+                                         height as f32 / 2. / self.zoom);
+        let (left, right) = (self.pan.0 - half_width, self.pan.0 + half_width);
+        let (bottom, top) = (self.pan.1 - half_height, self.pan.1 + half_height);
+        let mut points = Vec::new();
+        {
+            let mut line = |start: (f32, f32), end: (f32, f32), color: color::Color| {
+                points.push(Point { coords: [start.0, start.1], color: to_array(color) });
+                points.push(Point { coords: [end.0, end.1], color: to_array(color) });
+            };
+            if spacing * self.zoom >= MIN_GRID_GAP {
+                let mut x = (left / spacing).floor() * spacing;
+                while x <= right {
+                    line((x, bottom), (x, top), color::GRID);
+                    x += spacing;
+                }
+                let mut y = (bottom / spacing).floor() * spacing;
+                while y <= top {
+                    line((left, y), (right, y), color::GRID);
+                    y += spacing;
+                }
+            }
+            // The axes come last so that they're drawn over the gridlines
+            line((0., bottom), (0., top), color::AXES);
+            line((left, 0.), (right, 0.), color::AXES);
+        }
         let vertex_buffer = glium::VertexBuffer::new(&self.window, &points);
         let indices = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
         let uniforms = uniform! { matrix: matrix };
@@ -298,9 +910,70 @@ impl TurtleScreen {
             .unwrap();
     }
 
-    fn draw_text(&self, frame: &mut glium::Frame, text: &Text) {
-        const FONT_SIZE: f32 = 12.;
-        let Text(pos_x, pos_y, angle_deg, text_color, ref data) = *text;
+    fn draw_fill<S: Surface>(&self, frame: &mut S, fill: &Fill, matrix: ScaleMatrix) {
+        let Fill(x, y, scale, ref texture) = *fill;
+        // The patch has one pixel per turtle unit at the given scale
+        let (width, height) = (texture.get_width() as f32 / scale,
+                               texture.get_height().unwrap() as f32 / scale);
+        let vertex_buffer = glium::VertexBuffer::new(
+            &self.window,
+            &vec![
+                // Bottom left corner
+                FerrisPoint { coords: [x, y - height], tex_coords: [0., 0.] },
+                // Bottom right corner
+                FerrisPoint { coords: [x + width, y - height], tex_coords: [1., 0.] },
+                // Top right corner
+                FerrisPoint { coords: [x + width, y], tex_coords: [1., 1.] },
+                // Top left corner
+                FerrisPoint { coords: [x, y], tex_coords: [0., 1.] },
+        ]);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+        let uniforms = uniform! {
+            matrix: matrix,
+            texture_data: texture,
+        };
+        frame.draw(&vertex_buffer.unwrap(), &indices, &self.patch_program, &uniforms,
+                   &Default::default()).unwrap();
+    }
+
+    /// Draw a single line that is not part of the line buffer
+    fn draw_line<S: Surface>(&self, frame: &mut S, line: &Line, matrix: ScaleMatrix) {
+        let vertex_buffer = glium::VertexBuffer::new(&self.window, &line_points(line));
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
+        let uniforms = uniform! { matrix: matrix };
+        frame.draw(&vertex_buffer.unwrap(), &indices, &self.program, &uniforms,
+                   &self.line_parameters()).unwrap();
+    }
+
+    /// Draw the lines whose vertices are in the given range of the line buffer
+    fn draw_lines<S: Surface>(&self, frame: &mut S, range: Range<usize>, matrix: ScaleMatrix) {
+        if range.start == range.end {
+            return;
+        }
+        let buffer = self.line_buffer.as_ref().expect("Line buffer is missing");
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
+        let uniforms = uniform! { matrix: matrix };
+        frame.draw(buffer.slice(range).unwrap(), &indices, &self.program, &uniforms,
+                   &self.line_parameters()).unwrap();
+    }
+
+    fn line_parameters(&self) -> glium::DrawParameters {
+        // Blending is needed so that transparent lines show what's below them,
+        // it is also needed for the smoothing
+        let smooth = if self.antialiasing {
+            Some(glium::draw_parameters::Smooth::Nicest)
+        } else {
+            None
+        };
+        glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            smooth: smooth,
+            .. Default::default()
+        }
+    }
+
+    fn draw_text<S: Surface>(&self, frame: &mut S, text: &Text) {
+        let Text(pos_x, pos_y, angle_deg, text_color, ref data, font_size, alignment) = *text;
         // Convert to radians
         let angle = ::std::f32::consts::PI * angle_deg / 180.;
         let sin_d = angle.sin();
@@ -313,32 +986,103 @@ impl TurtleScreen {
             sin_d, cos_d, 0., 0.,
             0., 0., 1., 0.,
             0., 0., 0., 1.);
+        // The alignment offset is applied before the rotation, so that it is
+        // along the text's baseline
+        let offset = match alignment {
+            Alignment::Left => 0.,
+            Alignment::Center => -text_display.get_width() / 2.,
+            Alignment::Right => -text_display.get_width(),
+        };
+        let align_matrix = na::Mat4::new(
+            1., 0., 0., offset,
+            0., 1., 0., 0.,
+            0., 0., 1., 0.,
+            0., 0., 0., 1.);
+        let (scale_x, scale_y) = (2. * self.zoom / width as f32, 2. * self.zoom / height as f32);
         let scale_matrix = na::Mat4::new(
-            2. * FONT_SIZE / width as f32, 0., 0., 0.,
-            0., 2. * FONT_SIZE / height as f32, 0., 0.,
+            font_size * scale_x, 0., 0., 0.,
+            0., font_size * scale_y, 0., 0.,
             0., 0., 1., 0.,
             0., 0., 0., 1.);
         let translate_matrix = na::Mat4::new(
-            1., 0., 0., pos_x * 2. / width as f32,
-            0., 1., 0., pos_y * 2. / height as f32,
+            1., 0., 0., (pos_x - self.pan.0) * scale_x,
+            0., 1., 0., (pos_y - self.pan.1) * scale_y,
             0., 0., 1., 0.,
             0., 0., 0., 1.);
         glium_text::draw(&text_display, &self.text_system, frame,
-                         *(translate_matrix * scale_matrix * rotation_matrix).as_ref(),
+                         *(translate_matrix * scale_matrix * rotation_matrix * align_matrix).as_ref(),
                          text_color);
     }
 
-    fn draw_turtle(&self, frame: &mut glium::Frame, matrix: ScaleMatrix) {
-        // WIDTH and HEIGHT specifiy the size in which Ferris should be drawn.
-        // The aspect ratio should be kept, the original Ferris image has a
-        // ratio of w:h 3:2
-        const WIDTH: f32 = 36.;
-        const HEIGHT: f32 = 24.;
-        const DX: f32 = WIDTH / 2.;
-        const DY: f32 = HEIGHT / 2.;
+    fn draw_turtle<S: Surface>(&self, frame: &mut S, matrix: ScaleMatrix) {
+        self.draw_turtle_shape(frame, &self.turtle_shape, self.turtle_position,
+                               self.turtle_orientation, self.turtle_color, self.turtle_scale,
+                               matrix);
+    }
+
+    fn draw_turtle_shape<S: Surface>(&self, frame: &mut S, shape: &TurtleShape,
+                                     position: (f32, f32), orientation: f32,
+                                     color: color::Color, scale: f32, matrix: ScaleMatrix) {
+        // The polygons are given in turtle coordinates, the turtle looks
+        // along the positive y axis
+        const TRIANGLE: [(f32, f32); 3] = [(0., 12.), (-9., -9.), (9., -9.)];
+        const CLASSIC: [(f32, f32); 4] = [(0., 12.), (-9., -9.), (0., -4.), (9., -9.)];
+        match *shape {
+            TurtleShape::Ferris =>
+                self.draw_turtle_texture(frame, &self.ferris, position, orientation, scale,
+                                         matrix),
+            TurtleShape::Image(ref texture) =>
+                self.draw_turtle_texture(frame, texture, position, orientation, scale, matrix),
+            TurtleShape::Triangle =>
+                self.draw_turtle_polygon(frame, &TRIANGLE, position, orientation, color, scale,
+                                         matrix),
+            TurtleShape::Classic =>
+                self.draw_turtle_polygon(frame, &CLASSIC, position, orientation, color, scale,
+                                         matrix),
+        }
+    }
 
-        let (tx, ty) = self.turtle_position;
-        let orientation_rad = ::std::f32::consts::PI * self.turtle_orientation / 180.0;
+    fn draw_turtle_polygon<S: Surface>(&self, frame: &mut S, polygon: &[(f32, f32)],
+                                       position: (f32, f32), orientation: f32,
+                                       color: color::Color, scale: f32, matrix: ScaleMatrix) {
+        let (tx, ty) = position;
+        let orientation_rad = ::std::f32::consts::PI * orientation / 180.0;
+        let sin_d = orientation_rad.sin();
+        let cos_d = orientation_rad.cos();
+        let points: Vec<Point> = polygon.iter()
+            .map(|&(x, y)| (x * scale, y * scale))
+            .map(|(x, y)| Point {
+                coords: [tx + x * cos_d - y * sin_d, ty + x * sin_d + y * cos_d],
+                color: color::to_array(color),
+            })
+            .collect();
+        let vertex_buffer = glium::VertexBuffer::new(&self.window, &points);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+        let uniforms = uniform! { matrix: matrix };
+        let parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        };
+        frame.draw(&vertex_buffer.unwrap(), &indices, &self.program, &uniforms, &parameters)
+            .unwrap();
+    }
+
+    fn draw_turtle_texture<S: Surface>(&self, frame: &mut S,
+                                       texture: &glium::texture::Texture2d,
+                                       position: (f32, f32), orientation: f32, scale: f32,
+                                       matrix: ScaleMatrix) {
+        // SIZE specifies the longer side of the drawn image at scale 1. The
+        // aspect ratio is kept, for the original Ferris image (ratio w:h 3:2)
+        // this gives 36x24
+        const SIZE: f32 = 36.;
+        let width = texture.get_width() as f32;
+        let height = texture.get_height().unwrap() as f32;
+        let factor = scale * SIZE / width.max(height);
+        let dx = width * factor / 2.;
+        let dy = height * factor / 2.;
+
+        let (tx, ty) = position;
+        let orientation_rad = ::std::f32::consts::PI * orientation / 180.0;
         let sin_d = orientation_rad.sin();
         let cos_d = orientation_rad.cos();
 
@@ -353,19 +1097,19 @@ impl TurtleScreen {
             &self.window,
             &vec![
                 // Bottom left corner
-                FerrisPoint { coords: [tx - DX, ty - DY], tex_coords: [0., 0.] },
+                FerrisPoint { coords: [tx - dx, ty - dy], tex_coords: [0., 0.] },
                 // Bottom right corner
-                FerrisPoint { coords: [tx + DX, ty - DY], tex_coords: [1., 0.] },
+                FerrisPoint { coords: [tx + dx, ty - dy], tex_coords: [1., 0.] },
                 // Top right corner
-                FerrisPoint { coords: [tx + DX, ty + DY], tex_coords: [1., 1.] },
+                FerrisPoint { coords: [tx + dx, ty + dy], tex_coords: [1., 1.] },
                 // Top left corner
-                FerrisPoint { coords: [tx - DX, ty + DY], tex_coords: [0., 1.] },
+                FerrisPoint { coords: [tx - dx, ty + dy], tex_coords: [0., 1.] },
         ]);
         let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
         let uniforms = uniform! {
             matrix: matrix,
             rotation_matrix: rotation_matrix,
-            ferris_tex: &self.ferris,
+            ferris_tex: texture,
             tip_x: tx,
             tip_y: ty,
         };
@@ -374,13 +1118,112 @@ impl TurtleScreen {
     }
 
     /// Poll the window's events and handle them
+    ///
+    /// Scrolling the mouse wheel changes the zoom, the arrow keys move the view.
+    /// Left clicks are recorded and can be retrieved with `last_click`, the keys
+    /// that are held down can be checked with `is_key_pressed`. When the window
+    /// is resized, the screen is redrawn with the new dimensions so the drawing
+    /// stays centered and undistorted.
     pub fn handle_events(&mut self) {
-        use glium::glutin::Event;
+        use glium::glutin::{Event, ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+        let mut resized = false;
         for event in self.window.poll_events() {
-            if let Event::Closed =  event {
-                self._is_closed = true;
-                self.window.get_window().unwrap().hide();
+            match event {
+                Event::Resized(..) => resized = true,
+                Event::Closed => {
+                    self._is_closed = true;
+                    self.window.get_window().unwrap().hide();
+                },
+                Event::MouseWheel(delta) => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        // Roughly the height of a line
+                        MouseScrollDelta::PixelDelta(_, y) => y / 20.,
+                    };
+                    let zoom = self.zoom * ZOOM_STEP.powf(lines);
+                    self.set_zoom(zoom.max(MIN_WHEEL_ZOOM).min(MAX_WHEEL_ZOOM));
+                },
+                Event::MouseMoved(position) => {
+                    self.cursor_position = position;
+                },
+                Event::MouseInput(ElementState::Pressed, MouseButton::Left) => {
+                    let (x, y) = self.cursor_position;
+                    let dimensions = self.window.get_framebuffer_dimensions();
+                    self.last_click = Some(self.to_turtle_coordinates((x as f32, y as f32),
+                                                                      dimensions));
+                },
+                Event::KeyboardInput(ElementState::Released, _, Some(key)) => {
+                    self.pressed_keys.remove(&key);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(key)) => {
+                    self.pressed_keys.insert(key);
+                    // Move by the same amount of pixels regardless of the zoom
+                    let step = PAN_STEP / self.zoom;
+                    match key {
+                        VirtualKeyCode::Left => self.pan_by(-step, 0.),
+                        VirtualKeyCode::Right => self.pan_by(step, 0.),
+                        VirtualKeyCode::Up => self.pan_by(0., step),
+                        VirtualKeyCode::Down => self.pan_by(0., -step),
+                        _ => (),
+                    }
+                    if Some(key) == self.pause_key {
+                        self.paused = !self.paused;
+                    }
+                    if Some(key) == self.screenshot_key {
+                        let result = self.save_screenshot();
+                        self.screenshot_results.push(result);
+                    }
+                },
+                _ => (),
+            }
+        }
+        if resized && !self._is_closed {
+            self.draw_and_update();
+        }
+    }
+
+    /// Return true if the given key is currently held down. The state of the
+    /// keys is only updated by `handle_events`.
+    pub fn is_key_pressed(&self, key: glium::glutin::VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    /// Return the position (in turtle coordinates) of the last left click
+    /// inside the window, or `None` if there was no click yet.
+    pub fn last_click(&self) -> Option<(f32, f32)> {
+        self.last_click
+    }
+
+    /// Block until the user clicks into the window and return the position of
+    /// the click in turtle coordinates. The window is kept updated while
+    /// waiting. Returns `None` if the window is closed before a click happens.
+    pub fn wait_for_click(&mut self) -> Option<(f32, f32)> {
+        self.last_click = None;
+        loop {
+            self.draw_and_update();
+            self.handle_events();
+            if self.is_closed() {
+                return None;
             }
+            if let Some(position) = self.last_click {
+                return Some(position);
+            }
+            thread::sleep(time::Duration::from_millis(1000 / 30));
+        }
+    }
+
+    /// Wait for the given amount of seconds. The window is updated before and
+    /// kept responsive while waiting. If the window is closed, the waiting
+    /// stops early.
+    pub fn sleep(&mut self, seconds: f32) {
+        const FRAME_MILLIS: u64 = 1000 / 30;
+        let mut remaining = (seconds.max(0.) * 1000.) as u64;
+        self.draw_and_update();
+        while remaining > 0 && !self.is_closed() {
+            self.handle_events();
+            let step = remaining.min(FRAME_MILLIS);
+            thread::sleep(time::Duration::from_millis(step));
+            remaining -= step;
         }
     }
 
@@ -391,10 +1234,183 @@ impl TurtleScreen {
         self._is_closed
     }
 
+    /// Return if the screen has been paused with `pause_key`. Like `is_closed`,
+    /// this only changes when the window's events are handled. It is up to the
+    /// caller to stop while the screen is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the screen
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Return the results of the screenshots taken with `screenshot_key` since
+    /// the last call, i.e. the path of each saved file or the error that
+    /// occurred while saving it
+    pub fn take_screenshot_results(&mut self) -> Vec<image::ImageResult<PathBuf>> {
+        mem::replace(&mut self.screenshot_results, Vec::new())
+    }
+
+    /// Save a screenshot as PNG file in `screenshot_directory`. The file name
+    /// contains the current time, the path of the file is returned.
+    pub fn save_screenshot(&mut self) -> image::ImageResult<PathBuf> {
+        let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH)
+            .unwrap_or(time::Duration::from_secs(0));
+        let name = format!("rurtle-{}-{:03}.png", now.as_secs(), now.subsec_nanos() / 1000000);
+        let path = self.screenshot_directory.join(name);
+        let shot = self.screenshot();
+        let mut file = try!(fs::File::create(&path));
+        try!(shot.save(&mut file, image::ImageFormat::PNG));
+        Ok(path)
+    }
+
     /// Return the current screen as an image
-    pub fn screenshot(&self) -> image::DynamicImage {
-        raw_image_to_image(self.window.read_front_buffer())
+    ///
+    /// The grid is not part of the screenshot unless `grid_in_screenshots` is
+    /// set.
+    pub fn screenshot(&mut self) -> image::DynamicImage {
+        let grid = self.grid;
+        if !self.grid_in_screenshots {
+            self.grid = None;
+        }
+        let shot = self.render_to_image();
+        self.grid = grid;
+        shot
+    }
+
+    /// Like `screenshot`, but only return the part of the screen that contains
+    /// the drawing (see `bounding_box`), with a small margin. Texts and stamps
+    /// may be cut off, as only their anchor point is taken into account. If
+    /// nothing has been drawn, the whole screen is returned.
+    pub fn screenshot_cropped(&mut self) -> image::DynamicImage {
+        // Margin in pixels, so that smoothed edges of lines aren't cut off
+        const MARGIN: f32 = 2.;
+        let mut shot = self.screenshot();
+        if self.shapes.is_empty() {
+            return shot;
+        }
+        let (min_x, min_y, max_x, max_y) = self.bounding_box();
+        let (width, height) = shot.dimensions();
+        // The image's y-axis points downwards, so the top left corner comes
+        // from the maximum y coordinate
+        let (left, top) = self.to_image_coordinates((min_x, max_y), (width, height));
+        let (right, bottom) = self.to_image_coordinates((max_x, min_y), (width, height));
+        let clamp = |value: f32, max: u32| value.max(0.).min(max as f32) as u32;
+        let (left, top) = (clamp(left - MARGIN, width), clamp(top - MARGIN, height));
+        let (right, bottom) = (clamp(right + MARGIN + 1., width),
+                               clamp(bottom + MARGIN + 1., height));
+        shot.crop(left, top, right.saturating_sub(left), bottom.saturating_sub(top))
+    }
+}
+
+/// Return the image format that matches the extension of the given file name.
+/// PNG, JPEG, BMP and GIF are recognized, everything else is saved as PNG.
+pub fn image_format_for(filename: &str) -> image::ImageFormat {
+    let extension = ::std::path::Path::new(filename).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension.as_ref().map(|extension| extension.as_ref()) {
+        Some("jpg") | Some("jpeg") => image::ImageFormat::JPEG,
+        Some("bmp") => image::ImageFormat::BMP,
+        Some("gif") => image::ImageFormat::GIF,
+        _ => image::ImageFormat::PNG,
+    }
+}
+
+/// Return the key with the given name, e.g. "a", "5", "space" or "left". Letters
+/// are case-insensitive. Returns `None` if there is no key with this name.
+pub fn key_from_name(name: &str) -> Option<glium::glutin::VirtualKeyCode> {
+    use glium::glutin::VirtualKeyCode::*;
+    Some(match name.to_lowercase().as_ref() {
+        "a" => A,
+        "b" => B,
+        "c" => C,
+        "d" => D,
+        "e" => E,
+        "f" => F,
+        "g" => G,
+        "h" => H,
+        "i" => I,
+        "j" => J,
+        "k" => K,
+        "l" => L,
+        "m" => M,
+        "n" => N,
+        "o" => O,
+        "p" => P,
+        "q" => Q,
+        "r" => R,
+        "s" => S,
+        "t" => T,
+        "u" => U,
+        "v" => V,
+        "w" => W,
+        "x" => X,
+        "y" => Y,
+        "z" => Z,
+        "0" => Key0,
+        "1" => Key1,
+        "2" => Key2,
+        "3" => Key3,
+        "4" => Key4,
+        "5" => Key5,
+        "6" => Key6,
+        "7" => Key7,
+        "8" => Key8,
+        "9" => Key9,
+        "space" => Space,
+        "enter" | "return" => Return,
+        "escape" => Escape,
+        "tab" => Tab,
+        "backspace" => Back,
+        "up" => Up,
+        "down" => Down,
+        "left" => Left,
+        "right" => Right,
+        _ => return None,
+    })
+}
+
+/// Split the line from start to end into the segments that need to be drawn for
+/// the given style. Returns a list of points where each two consecutive points
+/// form a segment, suitable for `PrimitiveType::LinesList`.
+fn line_segments(start: (f32, f32), end: (f32, f32), style: LineStyle) -> Vec<(f32, f32)> {
+    let (drawn, gap) = match style {
+        LineStyle::Solid => return vec![start, end],
+        LineStyle::Dashed => (DASH_LENGTH, DASH_LENGTH),
+        LineStyle::Dotted => (DOT_LENGTH, DOT_GAP),
+    };
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0. {
+        return vec![start, end];
     }
+    // We stretch the pattern slightly so that a whole number of periods fits
+    // into the line. That way both ends of the line are drawn and lines of
+    // similar length look alike.
+    let periods = ((length + gap) / (drawn + gap)).round().max(1.);
+    let period = (length + gap) / periods;
+    let ratio = drawn / (drawn + gap);
+    let mut points = Vec::new();
+    for i in 0..periods as u32 {
+        let from = i as f32 * period / length;
+        let to = ((i as f32 + ratio) * period / length).min(1.);
+        points.push((start.0 + from * dx, start.1 + from * dy));
+        points.push((start.0 + to * dx, start.1 + to * dy));
+    }
+    points
+}
+
+/// Return the vertices (pairs of start and end points) needed to draw the
+/// given line
+fn line_points(line: &Line) -> Vec<Point> {
+    let Line(x1, y1, x2, y2, color, style) = *line;
+    line_segments((x1, y1), (x2, y2), style)
+        .into_iter()
+        .map(|(x, y)| Point { coords: [x, y], color: color::to_array(color) })
+        .collect()
 }
 
 /// Convert an image::DynamicImage to a glium::texture::Texture2d
@@ -407,6 +1423,12 @@ fn image_to_texture<F: glium::backend::Facade>(display: &F, im: image::DynamicIm
     glium::texture::Texture2d::new(display, glium_image)
 }
 
+/// Turn the error of a failed texture creation, e.g. because the image is
+/// too big, into an image::ImageError
+fn texture_error(error: glium::texture::TextureCreationError) -> image::ImageError {
+    image::ImageError::UnsupportedError(format!("Can't use the image as texture: {:?}", error))
+}
+
 /// Convert a glium::texture::RawImage2d to an image::DynamicImage
 fn raw_image_to_image(tex: glium::texture::RawImage2d<u8>) -> image::DynamicImage {
     assert_eq!(tex.format, glium::texture::ClientFormat::U8U8U8U8);
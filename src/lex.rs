@@ -12,7 +12,13 @@
 //!
 //! Variables are prefixed by a colon (:) and otherwise follow the same rules as
 //! identifiers.
+//!
+//! Comments either start with a semicolon (;) and go until the end of the line,
+//! or they are enclosed in #| and |#. The latter may span multiple lines and may
+//! be nested.
 use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::str;
 
 /// A `Token` represents a "atom" block of the input source.
 #[derive(Debug, Clone, PartialEq)]
@@ -30,6 +36,8 @@ pub enum Token {
     RParens,
     /// The colon :
     Colon,
+    /// The comma ,
+    Comma,
     /// A String enclosed in "quotes"
     String(String),
     /// Operator "equals" =
@@ -60,8 +68,12 @@ pub enum Token {
     KeyDo,
     /// Keyword "ELSE"
     KeyElse,
+    /// Keyword "ELSEIF"
+    KeyElseIf,
     /// Keyword "REPEAT"
     KeyRepeat,
+    /// Keyword "WITH"
+    KeyWith,
     /// Keyword "WHILE"
     KeyWhile,
     /// Keyword "IF"
@@ -74,6 +86,12 @@ pub enum Token {
     KeyReturn,
     /// Keyword "TRY"
     KeyTry,
+    /// Keyword "AND"
+    KeyAnd,
+    /// Keyword "OR"
+    KeyOr,
+    /// Keyword "NOT"
+    KeyNot,
 }
 
 impl ::std::fmt::Display for Token {
@@ -88,6 +106,7 @@ impl ::std::fmt::Display for Token {
             LParens => "left parenthesis",
             RParens => "right parenthesis",
             Colon => "colon",
+            Comma => "comma",
             String(_) => "string literal",
             _ => &debug,
         })
@@ -101,33 +120,49 @@ pub struct MetaToken {
     pub token: Token,
     /// Line number in which the token was found. Lines start with 1.
     pub line_number: u32,
+    /// Column in which the token starts. Columns start with 1 and count
+    /// characters, not bytes.
+    pub column: u32,
 }
 
-/// Errors that may arise when lexing the input. The first member is always the line number.
+/// Errors that may arise when lexing the input. The first two members are
+/// always the line number and the column where the error occured.
 #[derive(Debug)]
 pub enum LexError {
     /// Unterminated string/closing quotes missing
-    UnterminatedString(u32),
+    UnterminatedString(u32, u32),
     /// Invalid number literal
-    InvalidNumber(u32, String),
-    UnexpectedCharacter(u32, char),
+    InvalidNumber(u32, u32, String),
+    UnexpectedCharacter(u32, u32, char),
+    /// Unterminated block comment/closing |# missing
+    UnterminatedComment(u32, u32),
+    /// Unknown escape sequence in a string literal
+    InvalidEscape(u32, u32, char),
 }
 impl ::std::fmt::Display for LexError {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
         match *self {
-            LexError::UnterminatedString(line) => {
-                try!(fmt.pad("unterminated string in line "));
-                line.fmt(fmt)
+            LexError::UnterminatedString(line, column) => {
+                let s = format!("unterminated string in line {}, column {}", line, column);
+                fmt.pad(&s)
+            },
+            LexError::InvalidNumber(line, column, ref s) => {
+                let s = format!("invalid number: {} in line {}, column {}", s, line, column);
+                fmt.pad(&s)
             },
-            LexError::InvalidNumber(line, ref s) => {
-                let s = format!("invalid number: {} in line {}", s, line);
+            LexError::UnexpectedCharacter(line, column, which) => {
+                let s = format!("unexpected character in line {}, column {}: {}",
+                                line, column, which);
                 fmt.pad(&s)
             },
-            LexError::UnexpectedCharacter(line, which) => {
-                try!(fmt.pad("unexpected character in line "));
-                try!(line.fmt(fmt));
-                try!(fmt.pad(": "));
-                fmt.pad(&which.to_string())
+            LexError::UnterminatedComment(line, column) => {
+                let s = format!("unterminated comment in line {}, column {}", line, column);
+                fmt.pad(&s)
+            },
+            LexError::InvalidEscape(line, column, which) => {
+                let s = format!("invalid escape sequence in line {}, column {}: \\{}",
+                                line, column, which);
+                fmt.pad(&s)
             },
         }
     }
@@ -138,6 +173,8 @@ impl ::std::error::Error for LexError {
             LexError::UnterminatedString(..) => "closing quotes are missing",
             LexError::InvalidNumber(..) => "invalid number literal",
             LexError::UnexpectedCharacter(..) => "unexpected character",
+            LexError::UnterminatedComment(..) => "closing |# is missing",
+            LexError::InvalidEscape(..) => "invalid escape sequence",
         }
     }
 }
@@ -150,9 +187,69 @@ fn is_identifier_cont(c: char) -> bool {
     is_identifier_start(c) || c.is_alphanumeric()
 }
 
+/// An iterator over the characters of the input that keeps track of the line
+/// and column of the next character.
+struct Chars<'a> {
+    chars: Peekable<str::Chars<'a>>,
+    line_number: u32,
+    column: u32,
+}
+
+impl<'a> Chars<'a> {
+    fn new(input: &'a str) -> Chars<'a> {
+        Chars {
+            chars: input.chars().peekable(),
+            line_number: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    /// Return the character `n` positions after the next one without
+    /// consuming anything, `lookahead(0)` is the same as `peek`
+    fn lookahead(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+
+    /// Return true if an exponent like `e5` or `E-3` follows
+    fn exponent_follows(&self) -> bool {
+        let is_digit = |c: Option<char>| c.map_or(false, |c| c.is_numeric());
+        match self.lookahead(0) {
+            Some('e') | Some('E') => match self.lookahead(1) {
+                Some('+') | Some('-') => is_digit(self.lookahead(2)),
+                c => is_digit(c),
+            },
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        match next {
+            Some('\n') => {
+                self.line_number += 1;
+                self.column = 1;
+            },
+            Some(_) => self.column += 1,
+            None => (),
+        }
+        next
+    }
+}
+
 struct Tokenizer {
     result: VecDeque<MetaToken>,
+    /// Line number of the current token's start
     line_number: u32,
+    /// Column of the current token's start
+    column: u32,
 }
 
 impl Tokenizer {
@@ -160,6 +257,7 @@ impl Tokenizer {
         Tokenizer {
             result: VecDeque::new(),
             line_number: 1,
+            column: 1,
         }
     }
 
@@ -167,22 +265,30 @@ impl Tokenizer {
         self.result.push_back(MetaToken {
             token: token,
             line_number: self.line_number,
+            column: self.column,
         })
     }
 
     #[cfg_attr(feature = "linted", allow(while_let_on_iterator))]
     fn tokenize(mut self, input: &str) -> Result<VecDeque<MetaToken>, LexError> {
-        let mut chars = input.chars().peekable();
+        let mut chars = Chars::new(input);
 
-        // This has to be a while-let loop because we might manually advance
-        // the iterator in the loop body. A for loop moves the iterator and
-        // makes this impossible.
-        while let Some(c) = chars.next() {
+        // This has to be a loop with a manual next() because we might manually
+        // advance the iterator in the loop body and we need to remember where
+        // each token starts.
+        loop {
+            self.line_number = chars.line_number;
+            self.column = chars.column;
+            let c = match chars.next() {
+                Some(c) => c,
+                None => break,
+            };
             match c {
                 '(' => self.push(Token::LParens),
                 ')' => self.push(Token::RParens),
                 '[' => self.push(Token::LBracket),
                 ']' => self.push(Token::RBracket),
+                ',' => self.push(Token::Comma),
                 ':' => {
                     if let Some(&'=') = chars.peek() {
                         chars.next().unwrap();
@@ -219,11 +325,39 @@ impl Tokenizer {
                 ';' => {
                     while let Some(c) = chars.next() {
                         if c == '\n' {
-                            self.line_number += 1;
                             break
                         }
                     }
                 },
+                // Ignore block comments, i.e. everything between #| and |#
+                '#' if chars.peek() == Some(&'|') => {
+                    chars.next().unwrap();
+                    let mut depth = 1;
+                    let mut previous = None;
+                    while let Some(c) = chars.next() {
+                        match (previous, c) {
+                            (Some('#'), '|') => {
+                                depth += 1;
+                                // The | can't also be the start of a closing |#
+                                previous = None;
+                                continue
+                            },
+                            (Some('|'), '#') => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break
+                                }
+                                previous = None;
+                                continue
+                            },
+                            _ => (),
+                        }
+                        previous = Some(c);
+                    }
+                    if depth > 0 {
+                        return Err(LexError::UnterminatedComment(self.line_number, self.column));
+                    }
+                },
                 // Parse an identifier or a keyword
                 _ if is_identifier_start(c) => {
                     let mut word = c.to_string();
@@ -239,18 +373,42 @@ impl Tokenizer {
                         "DO" => Token::KeyDo,
                         "END" => Token::KeyEnd,
                         "REPEAT" => Token::KeyRepeat,
+                        "WITH" => Token::KeyWith,
                         "FOR" => Token::KeyFor,
                         "IF" => Token::KeyIf,
                         "WHILE" => Token::KeyWhile,
                         "RETURN" => Token::KeyReturn,
                         "ELSE" => Token::KeyElse,
+                        "ELSEIF" => Token::KeyElseIf,
                         "TRY" => Token::KeyTry,
+                        "AND" => Token::KeyAnd,
+                        "OR" => Token::KeyOr,
+                        "NOT" => Token::KeyNot,
                         _ => Token::Word(word),
                     });
                 },
-                // Parse a number literal
-                _ if c.is_numeric() => {
+                // Parse a hexadecimal number literal
+                '0' if chars.peek() == Some(&'x') || chars.peek() == Some(&'X') => {
                     let mut number = c.to_string();
+                    number.push(chars.next().unwrap());
+                    while let Some(c) = chars.peek().cloned() {
+                        if c.is_alphanumeric() {
+                            number.push(chars.next().unwrap());
+                        } else {
+                            break
+                        }
+                    }
+                    match u32::from_str_radix(&number[2..], 16) {
+                        Ok(i) => self.push(Token::Number(i as f32)),
+                        Err(_) => return Err(LexError::InvalidNumber(self.line_number, self.column,
+                                                                     number)),
+                    }
+                },
+                // Parse a number literal, possibly with an exponent. A leading
+                // dot like in .5 is allowed as well.
+                _ if c.is_numeric() ||
+                     (c == '.' && chars.peek().map_or(false, |d| d.is_numeric())) => {
+                    let mut number = if c == '.' { "0.".to_owned() } else { c.to_string() };
                     while let Some(c) = chars.peek().cloned() {
                         if c.is_numeric() || c == '.' {
                             number.push(chars.next().unwrap());
@@ -258,9 +416,23 @@ impl Tokenizer {
                             break
                         }
                     }
+                    if chars.exponent_follows() {
+                        number.push(chars.next().unwrap());
+                        if chars.peek() == Some(&'-') || chars.peek() == Some(&'+') {
+                            number.push(chars.next().unwrap());
+                        }
+                        while let Some(c) = chars.peek().cloned() {
+                            if c.is_numeric() {
+                                number.push(chars.next().unwrap());
+                            } else {
+                                break
+                            }
+                        }
+                    }
                     match number.parse() {
                         Ok(f) => self.push(Token::Number(f)),
-                        Err(_) => return Err(LexError::InvalidNumber(self.line_number, number)),
+                        Err(_) => return Err(LexError::InvalidNumber(self.line_number, self.column,
+                                                                     number)),
                     }
                 },
                 // Parse a String literal
@@ -275,15 +447,10 @@ impl Tokenizer {
                                 terminated = true;
                                 break;
                             },
-                            '\n' if !escaped => {
-                                self.line_number += 1;
-                                string.push(c);
-                            },
                             '\\' if !escaped => {
                                 escaped = true;
                             }
                             '\n' if escaped => {
-                                self.line_number += 1;
                                 escaped = false;
                             },
                             'n' if escaped => {
@@ -294,6 +461,23 @@ impl Tokenizer {
                                 string.push('\r');
                                 escaped = false;
                             }
+                            't' if escaped => {
+                                string.push('\t');
+                                escaped = false;
+                            },
+                            '0' if escaped => {
+                                string.push('\0');
+                                escaped = false;
+                            },
+                            '"' | '\\' if escaped => {
+                                string.push(c);
+                                escaped = false;
+                            },
+                            _ if escaped => {
+                                // The backslash and c are both on the current line
+                                return Err(LexError::InvalidEscape(chars.line_number,
+                                                                   chars.column - 2, c));
+                            },
                             _ => {
                                 string.push(c);
                                 escaped = false;
@@ -301,12 +485,11 @@ impl Tokenizer {
                         }
                     }
                     if !terminated {
-                        return Err(LexError::UnterminatedString(self.line_number));
+                        return Err(LexError::UnterminatedString(self.line_number, self.column));
                     }
                 },
-                '\n' => self.line_number += 1,
                 _ if c.is_whitespace() => {},
-                _ => return Err(LexError::UnexpectedCharacter(self.line_number, c)),
+                _ => return Err(LexError::UnexpectedCharacter(self.line_number, self.column, c)),
             }
         }
         Ok(self.result)
@@ -319,3 +502,92 @@ pub fn tokenize(input: &str) -> Result<VecDeque<MetaToken>, LexError> {
     let tokenizer = Tokenizer::new();
     tokenizer.tokenize(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, LexError, Token};
+
+    #[test]
+    fn test_token_position() {
+        let tokens = tokenize("forward 10\n  right 90").unwrap();
+        let positions: Vec<_> = tokens.iter().map(|t| (t.line_number, t.column)).collect();
+        assert_eq!(positions, vec![(1, 1), (1, 9), (2, 3), (2, 9)]);
+        assert_eq!(tokens[2].token, Token::Word("right".to_owned()));
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        match tokenize("print\n  \"hello") {
+            Err(LexError::UnterminatedString(2, 3)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_character() {
+        match tokenize("\t$") {
+            Err(LexError::UnexpectedCharacter(1, 2, '$')) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Columns count characters, not bytes
+        match tokenize("\"äöü\" $") {
+            Err(LexError::UnexpectedCharacter(1, 7, '$')) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn number(input: &str) -> f32 {
+        match tokenize(input).unwrap()[0].token {
+            Token::Number(num) => num,
+            ref token => panic!("not a number: {:?}", token),
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(number("42"), 42.);
+        assert_eq!(number("0xFF"), 255.);
+        assert_eq!(number("1e3"), 1000.);
+        assert_eq!(number("1e-3"), 0.001);
+        assert_eq!(number("2.5e-1"), 0.25);
+        assert_eq!(number("2.5E+2"), 250.);
+        assert_eq!(number(".5"), 0.5);
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        match tokenize("0x") {
+            Err(LexError::InvalidNumber(1, 1, ref number)) if number == "0x" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match tokenize("0xZZ") {
+            Err(LexError::InvalidNumber(1, 1, ref number)) if number == "0xZZ" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match tokenize("1.2.3") {
+            Err(LexError::InvalidNumber(1, 1, _)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tokens = tokenize(r#""a\tb\0c\\d\"e\nf""#).unwrap();
+        assert_eq!(tokens[0].token, Token::String("a\tb\0c\\d\"e\nf".to_owned()));
+    }
+
+    #[test]
+    fn test_invalid_escape() {
+        match tokenize("print \"ab\\qc\"") {
+            Err(LexError::InvalidEscape(1, 10, 'q')) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_e_without_exponent() {
+        let tokens: Vec<_> = tokenize("3e 3e+").unwrap().into_iter().map(|t| t.token).collect();
+        assert_eq!(tokens, vec![Token::Number(3.), Token::Word("e".to_owned()),
+                                Token::Number(3.), Token::Word("e".to_owned()), Token::OpPlus]);
+    }
+}
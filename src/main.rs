@@ -19,17 +19,103 @@ pub mod floodfill;
 use std::{env, fs, thread, time};
 use std::error::Error;
 use std::io::Read;
+use std::path::Path;
 use std::sync::mpsc;
 
 const PROMPT: &'static str = "Rurtle> ";
+/// Prompt that is shown when the input continues on the next line
+const CONTINUATION_PROMPT: &'static str = "   ...  ";
+
+/// Check if the given input has unclosed blocks (or an unclosed block comment)
+/// and thus needs more lines before it can be evaluated.
+fn needs_more_input(source: &str) -> bool {
+    use lex::Token::*;
+    let tokens = match lex::tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(lex::LexError::UnterminatedComment(..)) => return true,
+        // Other errors are reported when the input is evaluated
+        Err(_) => return false,
+    };
+    let mut depth = 0;
+    for meta in tokens {
+        match meta.token {
+            KeyLearn | KeyIf | KeyRepeat | KeyWhile | KeyTry | KeyFor => depth += 1,
+            KeyEnd => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+/// Save the given frames as numbered PNG files in the given directory
+fn save_frames(directory: &str, frames: Vec<image::DynamicImage>) {
+    if let Err(e) = fs::create_dir_all(directory) {
+        println!("[error] Can't create {}: {}", directory, e);
+        return
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let path = Path::new(directory).join(format!("frame{:05}.png", i));
+        let result = fs::File::create(&path)
+            .map_err(image::ImageError::IoError)
+            .and_then(|mut file| frame.save(&mut file, image::ImageFormat::PNG));
+        if let Err(e) = result {
+            println!("[error] Can't save {}: {}", path.display(), e);
+            return
+        }
+    }
+}
+
+/// Parse a window size given as WIDTHxHEIGHT, e.g. 800x600
+fn parse_size(input: &str) -> Option<(u32, u32)> {
+    let mut parts = input.split('x');
+    let size = match (parts.next(), parts.next(), parts.next()) {
+        (Some(width), Some(height), None) => (width.parse().ok(), height.parse().ok()),
+        _ => return None,
+    };
+    match size {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
 
 fn main() {
+    // --frames <dir> saves a frame after each drawing operation while the
+    // given files are executed
+    let mut frames_dir = None;
+    // --size WxH sets the size of the window
+    let mut size = (640, 640);
+    let mut filenames = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--frames" {
+            match args.next() {
+                Some(dir) => frames_dir = Some(dir),
+                None => {
+                    println!("--frames needs a directory");
+                    return
+                },
+            }
+        } else if arg == "--size" {
+            match args.next().as_ref().and_then(|s| parse_size(s)) {
+                Some(s) => size = s,
+                None => {
+                    println!("--size needs the size as WIDTHxHEIGHT, e.g. --size 800x600");
+                    return
+                },
+            }
+        } else {
+            filenames.push(arg);
+        }
+    }
     let mut environ = {
-        let screen = graphic::TurtleScreen::new((640, 640), "Rurtle");
+        let screen = graphic::TurtleScreen::new(size, "Rurtle");
         let turtle = turtle::Turtle::new(screen);
         environ::Environment::new(turtle)
     };
-    for filename in env::args().skip(1) {
+    if frames_dir.is_some() {
+        environ.get_turtle().get_screen().start_recording();
+    }
+    for filename in filenames {
         let mut file = fs::File::open(&filename).unwrap();
         let mut source = String::new();
         file.read_to_string(&mut source).unwrap();
@@ -39,6 +125,10 @@ fn main() {
             return
         }
     };
+    if let Some(dir) = frames_dir {
+        let frames = environ.get_turtle().get_screen().stop_recording();
+        save_frames(&dir, frames);
+    }
     let (tx, rx) = mpsc::channel();
     // We use the hermes channel to make the "read thread" wait before printing
     // the next prompt and to signal it when the window closed.
@@ -47,12 +137,25 @@ fn main() {
     // Thread to do the blocking read so we can keep updating the window in the
     // main thread
     let guard = thread::spawn(move || {
+        let mut source = String::new();
         loop {
-            let input = readline::readline(PROMPT);
+            let prompt = if source.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+            let input = readline::readline(prompt);
             match input {
-                Some(string) => tx.send(string).unwrap(),
+                Some(string) => {
+                    if !source.is_empty() {
+                        source.push('\n');
+                    }
+                    source.push_str(&string);
+                },
                 None => break,
             }
+            // Keep reading until all blocks are closed
+            if needs_more_input(&source) {
+                continue;
+            }
+            tx.send(source).unwrap();
+            source = String::new();
             match hermes_in.recv() {
                 Ok(false) => (),
                 // Ok(true) means the window closed and we should exit
@@ -79,10 +182,10 @@ fn main() {
         if let Err(e) = environ.eval_source(&source) {
             println!("{}: {}", e.description(), e);
         }
-        let screen = environ.get_turtle().get_screen();
-        screen.draw_and_update();
-        screen.handle_events();
-        if screen.is_closed() {
+        environ.get_turtle().get_screen().draw_and_update();
+        environ.get_turtle().get_screen().handle_events();
+        environ.report_screenshots();
+        if environ.get_turtle().get_screen().is_closed() {
             println!("\n\nWindow closed, press enter to exit...");
             break;
         }
@@ -7,14 +7,21 @@
 //! The ast itself does not provide any methods to run or evaluate the program,
 //! it is merely a method to represent the program in memory. See the
 //! `environ::Environment::eval` method to find a way to execute the program.
-#[derive(Debug, Clone)]
+//!
+//! A node can however be turned back into Rurtle source code by using its
+//! `Display` implementation. The output is not necessarily the same as the
+//! original source (comments and formatting are lost), but it parses to an
+//! equivalent tree.
+use std::{fmt, iter};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     /// A list of statements as found inside a loop body
     StatementList(Vec<Node>),
     /// The if conditional (expression, true-clause, maybe false-clause)
     IfStatement(Box<Node>, Box<Node>, Option<Box<Node>>),
-    /// The repeat statement (count, loop body)
-    RepeatStatement(Box<Node>, Box<Node>),
+    /// The repeat statement (count, name of the index variable, loop body)
+    RepeatStatement(Box<Node>, Option<String>, Box<Node>),
     /// The while statement (condition, loop body)
     WhileStatement(Box<Node>, Box<Node>),
     /// The function definition statement (func name, func arg names, func body)
@@ -24,6 +31,13 @@ pub enum Node {
     /// occurs
     TryStatement(Box<Node>, Box<Node>),
     Comparison(Box<Node>, CompOp, Box<Node>),
+    /// A logical connection of two conditions. The second operand is only
+    /// evaluated if it is needed to determine the result.
+    LogicalOp(Box<Node>, LogicOp, Box<Node>),
+    /// Logical negation of the operand
+    Not(Box<Node>),
+    /// Arithmetic negation (unary minus) of the operand
+    Negate(Box<Node>),
     /// Addition or subtraction. One addition may hold more than one operation.
     Addition(Box<Node>, Vec<(AddOp, Node)>),
     /// Multiplication and division. One multiplication may hole more than one
@@ -34,6 +48,9 @@ pub enum Node {
     ReturnStatement(Box<Node>),
     Assignment(String, Box<Node>),
     List(Vec<Node>),
+    /// A list built by evaluating an expression for each value of a variable
+    /// (variable name, first value, last value, expression)
+    ListComprehension(String, Box<Node>, Box<Node>, Box<Node>),
     StringLiteral(String),
     Number(f32),
     Variable(String),
@@ -83,8 +100,9 @@ impl Node {
                     IfStatement(Box::new(cond.flatten()), Box::new(true_body.flatten()), None)
                 }
             },
-            RepeatStatement(count, body) => RepeatStatement(Box::new(count.flatten()),
-                                                            Box::new(body.flatten())),
+            RepeatStatement(count, index, body) => RepeatStatement(Box::new(count.flatten()),
+                                                                   index,
+                                                                   Box::new(body.flatten())),
             WhileStatement(cond, body) => WhileStatement(Box::new(cond.flatten()),
                                                          Box::new(body.flatten())),
             LearnStatement(name, args, body) => LearnStatement(name, args,
@@ -94,16 +112,247 @@ impl Node {
             Comparison(operand1, op, operand2) => Comparison(Box::new(operand1.flatten()),
                                                              op,
                                                              Box::new(operand2.flatten())),
+            LogicalOp(operand1, op, operand2) => LogicalOp(Box::new(operand1.flatten()),
+                                                           op,
+                                                           Box::new(operand2.flatten())),
+            Not(operand) => Not(Box::new(operand.flatten())),
+            Negate(operand) => Negate(Box::new(operand.flatten())),
             ReturnStatement(value) => ReturnStatement(Box::new(value.flatten())),
             FuncCall(name, args) => FuncCall(name, flatten(args)),
             Assignment(name, value) => Assignment(name, Box::new(value.flatten())),
+            ListComprehension(name, from, to, body) =>
+                ListComprehension(name, Box::new(from.flatten()), Box::new(to.flatten()),
+                                  Box::new(body.flatten())),
             node => node,
         }
     }
 }
 
+/// Helper function to escape a string so that it can be used as a string
+/// literal in Rurtle source code
+fn escape_string(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\0' => result.push_str("\\0"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+impl Node {
+    /// Returns true if the node can be written as an operand or function
+    /// argument without being enclosed in parenthesis
+    fn is_atomic(&self) -> bool {
+        use self::Node::*;
+        match *self {
+            List(_) | ListComprehension(..) | StringLiteral(_) | Variable(_) => true,
+            Number(num) => num >= 0.0,
+            FuncCall(_, ref args) => args.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn write_operand(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_atomic() {
+            self.write_expression(fmt)
+        } else {
+            try!(fmt.write_str("("));
+            try!(self.write_expression(fmt));
+            fmt.write_str(")")
+        }
+    }
+
+    fn write_expression(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::Node::*;
+        match *self {
+            Comparison(ref a, op, ref b) => {
+                try!(a.write_operand(fmt));
+                try!(write!(fmt, " {} ", op));
+                b.write_operand(fmt)
+            },
+            LogicalOp(ref a, op, ref b) => {
+                try!(a.write_operand(fmt));
+                try!(write!(fmt, " {} ", op));
+                b.write_operand(fmt)
+            },
+            Not(ref operand) => {
+                try!(fmt.write_str("NOT "));
+                operand.write_operand(fmt)
+            },
+            Negate(ref operand) => {
+                try!(fmt.write_str("-"));
+                operand.write_operand(fmt)
+            },
+            Addition(ref start, ref values) => {
+                try!(start.write_operand(fmt));
+                for &(op, ref value) in values {
+                    try!(fmt.write_str(match op {
+                        AddOp::Add => " + ",
+                        AddOp::Sub => " - ",
+                    }));
+                    try!(value.write_operand(fmt));
+                }
+                Ok(())
+            },
+            Multiplication(ref start, ref values) => {
+                try!(start.write_operand(fmt));
+                for &(op, ref value) in values {
+                    try!(fmt.write_str(match op {
+                        MulOp::Mul => " * ",
+                        MulOp::Div => " / ",
+                    }));
+                    try!(value.write_operand(fmt));
+                }
+                Ok(())
+            },
+            FuncCall(ref name, ref args) => {
+                try!(fmt.write_str(name));
+                for arg in args {
+                    try!(fmt.write_str(" "));
+                    try!(arg.write_operand(fmt));
+                }
+                Ok(())
+            },
+            Assignment(ref name, ref value) => {
+                try!(write!(fmt, ":{} := ", name));
+                value.write_expression(fmt)
+            },
+            List(ref elements) => {
+                try!(fmt.write_str("["));
+                let mut first = true;
+                for element in elements {
+                    if !first { try!(fmt.write_str(" ")) };
+                    first = false;
+                    try!(element.write_operand(fmt));
+                }
+                fmt.write_str("]")
+            },
+            ListComprehension(ref name, ref from, ref to, ref body) => {
+                try!(write!(fmt, "[FOR :{} FROM ", name));
+                try!(from.write_expression(fmt));
+                try!(fmt.write_str(" TO "));
+                try!(to.write_expression(fmt));
+                try!(fmt.write_str(" DO "));
+                try!(body.write_expression(fmt));
+                fmt.write_str(" END]")
+            },
+            StringLiteral(ref string) => write!(fmt, "\"{}\"", escape_string(string)),
+            Number(num) => write!(fmt, "{}", num),
+            Variable(ref name) => write!(fmt, ":{}", name),
+            // Statements can't appear inside expressions
+            _ => self.write_statement(fmt, 0),
+        }
+    }
+
+    fn write_body(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        match *self {
+            Node::StatementList(ref statements) => {
+                for statement in statements {
+                    try!(statement.write_body(fmt, indent));
+                }
+                Ok(())
+            },
+            ref statement => statement.write_statement(fmt, indent),
+        }
+    }
+
+    /// Write an if statement without the leading IF and the closing END. An if
+    /// statement in the else branch is written as ELSEIF.
+    fn write_if_chain(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        if let Node::IfStatement(ref condition, ref true_body, ref false_body) = *self {
+            let prefix: String = iter::repeat(' ').take(4 * indent).collect();
+            try!(condition.write_expression(fmt));
+            try!(fmt.write_str(" DO\n"));
+            try!(true_body.write_body(fmt, indent + 1));
+            match *false_body {
+                Some(ref false_body) => {
+                    if let Node::IfStatement(..) = **false_body {
+                        try!(write!(fmt, "{}ELSEIF ", prefix));
+                        false_body.write_if_chain(fmt, indent)
+                    } else {
+                        try!(write!(fmt, "{}ELSE\n", prefix));
+                        false_body.write_body(fmt, indent + 1)
+                    }
+                },
+                None => Ok(()),
+            }
+        } else {
+            panic!("{:?} is not an IfStatement", self);
+        }
+    }
+
+    fn write_statement(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        use self::Node::*;
+        let prefix: String = iter::repeat(' ').take(4 * indent).collect();
+        try!(fmt.write_str(&prefix));
+        match *self {
+            StatementList(_) => {
+                // write_body already does the indentation for every statement
+                return self.write_body(fmt, indent);
+            },
+            IfStatement(..) => {
+                try!(fmt.write_str("IF "));
+                try!(self.write_if_chain(fmt, indent));
+            },
+            RepeatStatement(ref count, ref index, ref body) => {
+                try!(fmt.write_str("REPEAT "));
+                try!(count.write_expression(fmt));
+                if let Some(ref index) = *index {
+                    try!(write!(fmt, " WITH :{}", index));
+                }
+                try!(fmt.write_str(" DO\n"));
+                try!(body.write_body(fmt, indent + 1));
+            },
+            WhileStatement(ref condition, ref body) => {
+                try!(fmt.write_str("WHILE "));
+                try!(condition.write_expression(fmt));
+                try!(fmt.write_str(" DO\n"));
+                try!(body.write_body(fmt, indent + 1));
+            },
+            LearnStatement(ref name, ref args, ref body) => {
+                try!(write!(fmt, "LEARN {}", name));
+                for arg in args {
+                    try!(write!(fmt, " :{}", arg));
+                }
+                try!(fmt.write_str(" DO\n"));
+                try!(body.write_body(fmt, indent + 1));
+            },
+            TryStatement(ref normal, ref exception) => {
+                try!(fmt.write_str("TRY\n"));
+                try!(normal.write_body(fmt, indent + 1));
+                try!(write!(fmt, "{}ELSE\n", prefix));
+                try!(exception.write_body(fmt, indent + 1));
+            },
+            ReturnStatement(ref value) => {
+                try!(fmt.write_str("RETURN "));
+                try!(value.write_expression(fmt));
+                return fmt.write_str("\n");
+            },
+            _ => {
+                try!(self.write_expression(fmt));
+                return fmt.write_str("\n");
+            },
+        }
+        write!(fmt, "{}END\n", prefix)
+    }
+}
+
+impl fmt::Display for Node {
+    /// Write the node as Rurtle source code
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.write_statement(fmt, 0)
+    }
+}
+
 /// Different comparison operators
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CompOp {
     Equal, Less, Greater, LessEqual, GreaterEqual, NotEqual,
 }
@@ -141,7 +390,36 @@ impl CompOp {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+impl fmt::Display for CompOp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::CompOp::*;
+        fmt.pad(match *self {
+            Equal => "=",
+            Less => "<",
+            Greater => ">",
+            LessEqual => "<=",
+            GreaterEqual => ">=",
+            NotEqual => "<>",
+        })
+    }
+}
+
+/// Logical connectives
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LogicOp {
+    And, Or,
+}
+
+impl fmt::Display for LogicOp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.pad(match *self {
+            LogicOp::And => "AND",
+            LogicOp::Or => "OR",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum AddOp { Add, Sub }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum MulOp { Mul, Div }
@@ -11,6 +11,11 @@
 //! Depending on how many arguments each function takes, this may be parsed as
 //! either `funca(funcb(10))` or `funca(funcb(), 10)`.
 //!
+//! If a function name is directly followed by a left parenthesis (without any
+//! whitespace in between), the arguments are given explicitly, separated by
+//! commas or whitespace. `funca(funcb(), 10)` is therefore always parsed as
+//! written.
+//!
 //! # Grammar
 //!
 //! A EBNF-like (incomplete) grammar may look like
@@ -20,8 +25,9 @@
 //! statement := learn-def | if-stmt | repeat-stmt | while-stmt | return-stmt |
 //!              try-stmt | expression ;
 //! learn-def := 'LEARN' identifier {variable} 'DO' {statement} 'END' ;
-//! if-stmt := 'IF' expression 'DO' {statement} ['ELSE' {statement}]'END' ;
-//! repeat-stmt := 'REPEAT' expression 'DO' {statement} 'END' ;
+//! if-stmt := 'IF' if-chain 'END' ;
+//! if-chain := expression 'DO' {statement} ['ELSE' {statement} | 'ELSEIF' if-chain] ;
+//! repeat-stmt := 'REPEAT' expression ['WITH' variable] 'DO' {statement} 'END' ;
 //! while-stmt := 'WHILE' expression 'DO' {statement} 'END' ;
 //! return-stmt := 'RETURN' expression ;
 //! try-stmt := 'TRY' {statement} 'ELSE' {statement} 'END' ;
@@ -29,20 +35,27 @@
 //! identifier := idenfitier-start {identifier-cont} ;
 //! idenfitier-start := <any alphabetic character> ;
 //! idenfitier-cont := <any alpabetic or numeric character> ;
-//! expression := comparison ;
+//! expression := or-expr ;
+//! or-expr := and-expr {'OR' and-expr} ;
+//! and-expr := not-expr {'AND' not-expr} ;
+//! not-expr := 'NOT' not-expr | comparison ;
 //! comparison := expr [comp_op expr] ;
 //! comp_op := '=' | '<' | '>' | ''<=' | '>=' | '<>' ;
 //! expr := product {('+' | '-') product} ;
 //! product := factor {('*' | '/') factor} ;
-//! factor := '(' expression ')' | list | variable | string | number | (identifier {expression}) ;
-//! list := '[' {expression} ']' ;
+//! factor := '(' expression ')' | list | variable | string | number | func-call
+//!     | '-' factor ;
+//! func-call := identifier {expression} | identifier '(' [expression {[','] expression}] ')' ;
+//! list := '[' {expression} ']' | '[' list-comprehension ']' ;
+//! list-comprehension := 'FOR' variable 'FROM' expression 'TO' expression 'DO'
+//!     expression 'END' ;
 //! string := '"' {<any character>} '"' ;
 //! number := ['+' | '-'] <any valid floating point number literal> ;
 //! ```
 pub mod ast;
 
 use super::lex::{Token, MetaToken};
-use self::ast::{Node, AddOp, MulOp, CompOp};
+use self::ast::{Node, AddOp, MulOp, CompOp, LogicOp};
 use self::ast::Node::*;
 use std::collections::{HashMap, VecDeque};
 use std::{error, fmt};
@@ -62,6 +75,10 @@ pub enum ParseErrorKind {
     UnexpectedToken(&'static str, Token),
     UnexpectedEnd,
     UnknownFunction(String),
+    /// A function was called with explicit arguments (in parenthesis), but the
+    /// number of arguments is wrong. Contains the name of the function, the
+    /// number of arguments it takes and the number of arguments given.
+    WrongArgumentCount(String, i32, usize),
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -79,7 +96,11 @@ impl fmt::Display for ParseErrorKind {
             UnknownFunction(ref name) => {
                 try!(fmt.pad("unknown function: "));
                 name.fmt(fmt)
-            }
+            },
+            WrongArgumentCount(ref name, expected, got) => {
+                let plural = if expected == 1 { "" } else { "s" };
+                fmt.pad(&format!("{} takes {} argument{}, got {}", name, expected, plural, got))
+            },
         }
     }
 }
@@ -104,6 +125,7 @@ impl error::Error for ParseError {
             UnexpectedToken(..) => "unexpected token",
             UnexpectedEnd => "unexpected end",
             UnknownFunction(..) => "unknown function",
+            WrongArgumentCount(..) => "wrong argument count",
         }
     }
 }
@@ -198,6 +220,16 @@ impl Parser {
         None
     }
 
+    /// Check if the next token is a left parenthesis that starts exactly at the
+    /// given position.
+    fn parens_follow(&self, line: u32, column: u32) -> bool {
+        match self.tokens.front() {
+            Some(&MetaToken { token: Token::LParens, line_number, column: c }) =>
+                line_number == line && c == column,
+            _ => false,
+        }
+    }
+
     fn peek(&self) -> Token {
         self.tokens.front().unwrap().token.clone()
     }
@@ -226,7 +258,7 @@ impl Parser {
         let mut statements = Vec::new();
         while !self.tokens.is_empty() {
             match self.peek() {
-                Token::KeyElse | Token::KeyEnd => break,
+                Token::KeyElse | Token::KeyElseIf | Token::KeyEnd => break,
                 _ => {
                     statements.push(try!(self.parse_statement()));
                 },
@@ -278,24 +310,54 @@ impl Parser {
 
     fn parse_if_stmt(&mut self) -> ParseResult {
         expect!(self, Token::KeyIf);
+        let statement = try!(self.parse_if_chain());
+        expect!(self, Token::KeyEnd);
+        Ok(statement)
+    }
+
+    /// Parse an if statement after the IF (or ELSEIF) and without the closing
+    /// END. ELSEIF continues the chain, so that
+    /// `IF a DO .. ELSEIF b DO .. ELSE .. END` only needs a single END. The
+    /// result is a nested `IfStatement`.
+    fn parse_if_chain(&mut self) -> ParseResult {
         let condition = Box::new(try!(self.parse_expression()));
         expect!(self, Token::KeyDo);
         let true_body = Box::new(try!(self.parse_loop_body()));
-        let false_body = if let Token::KeyElse = self.peek() {
-            try!(self.pop_left());
-            Some(Box::new(try!(self.parse_loop_body())))
-        } else { None };
-        expect!(self, Token::KeyEnd);
+        if self.tokens.is_empty() {
+            parse_error!(self, UnexpectedEnd);
+        }
+        let false_body = match self.peek() {
+            Token::KeyElse => {
+                try!(self.pop_left());
+                Some(Box::new(try!(self.parse_loop_body())))
+            },
+            Token::KeyElseIf => {
+                try!(self.pop_left());
+                Some(Box::new(try!(self.parse_if_chain())))
+            },
+            _ => None,
+        };
         Ok(IfStatement(condition, true_body, false_body))
     }
 
     fn parse_repeat_stmt(&mut self) -> ParseResult {
         expect!(self, Token::KeyRepeat);
         let number = Box::new(try!(self.parse_expression()));
+        let mut index = None;
+        if !self.tokens.is_empty() {
+            if let Token::KeyWith = self.peek() {
+                try!(self.pop_left());
+                expect!(self, Token::Colon);
+                match try!(self.pop_left()) {
+                    Token::Word(name) => index = Some(name),
+                    token => parse_error!(self, UnexpectedToken("Token::Word", token)),
+                }
+            }
+        }
         expect!(self, Token::KeyDo);
         let body = try!(self.parse_loop_body());
         expect!(self, Token::KeyEnd);
-        Ok(RepeatStatement(number, Box::new(body)))
+        Ok(RepeatStatement(number, index, Box::new(body)))
     }
 
     fn parse_while_stmt(&mut self) -> ParseResult {
@@ -323,6 +385,44 @@ impl Parser {
     }
 
     fn parse_expression(&mut self) -> ParseResult {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> ParseResult {
+        let mut operand = try!(self.parse_and());
+        while !self.tokens.is_empty() {
+            if let Token::KeyOr = self.peek() {
+                try!(self.pop_left());
+                let operand_right = try!(self.parse_and());
+                operand = LogicalOp(Box::new(operand), LogicOp::Or, Box::new(operand_right));
+            } else {
+                break
+            }
+        }
+        Ok(operand)
+    }
+
+    fn parse_and(&mut self) -> ParseResult {
+        let mut operand = try!(self.parse_not());
+        while !self.tokens.is_empty() {
+            if let Token::KeyAnd = self.peek() {
+                try!(self.pop_left());
+                let operand_right = try!(self.parse_not());
+                operand = LogicalOp(Box::new(operand), LogicOp::And, Box::new(operand_right));
+            } else {
+                break
+            }
+        }
+        Ok(operand)
+    }
+
+    fn parse_not(&mut self) -> ParseResult {
+        if !self.tokens.is_empty() {
+            if let Token::KeyNot = self.peek() {
+                try!(self.pop_left());
+                return Ok(Not(Box::new(try!(self.parse_not()))));
+            }
+        }
         self.parse_comparison()
     }
 
@@ -388,10 +488,40 @@ impl Parser {
         Ok(Multiplication(factor, factors))
     }
 
+    /// Expect a word that is only a keyword at this position, like FROM in a
+    /// list comprehension
+    fn expect_word(&mut self, word: &'static str) -> Result<(), ParseError> {
+        match try!(self.pop_left()) {
+            Token::Word(ref name) if name.to_uppercase() == word => Ok(()),
+            token => parse_error!(self, UnexpectedToken(word, token)),
+        }
+    }
+
+    fn parse_list_comprehension(&mut self) -> ParseResult {
+        expect!(self, Token::KeyFor);
+        expect!(self, Token::Colon);
+        let name = match try!(self.pop_left()) {
+            Token::Word(name) => name,
+            token => parse_error!(self, UnexpectedToken("Token::Word", token)),
+        };
+        try!(self.expect_word("FROM"));
+        let from = try!(self.parse_expression());
+        try!(self.expect_word("TO"));
+        let to = try!(self.parse_expression());
+        expect!(self, Token::KeyDo);
+        let body = try!(self.parse_expression());
+        expect!(self, Token::KeyEnd);
+        Ok(ListComprehension(name, Box::new(from), Box::new(to), Box::new(body)))
+    }
+
     fn parse_factor(&mut self) -> ParseResult {
         if self.tokens.is_empty() {
             parse_error!(self, UnexpectedEnd);
         };
+        let (line, column) = {
+            let meta = self.tokens.front().unwrap();
+            (meta.line_number, meta.column)
+        };
         match try!(self.pop_left()) {
             Token::LParens => {
                 let factor = try!(self.parse_expression());
@@ -399,6 +529,13 @@ impl Parser {
                 Ok(factor)
             },
             Token::LBracket => {
+                if !self.tokens.is_empty() {
+                    if let Token::KeyFor = self.peek() {
+                        let comprehension = try!(self.parse_list_comprehension());
+                        expect!(self, Token::RBracket);
+                        return Ok(comprehension);
+                    }
+                }
                 let mut list = Vec::new();
                 while !self.tokens.is_empty() {
                     if let Token::RBracket = self.peek() {
@@ -426,6 +563,32 @@ impl Parser {
                     parse_error!(self, UnexpectedToken("Token::Word", try!(self.pop_left())))
                 }
             },
+            // A function call with explicit arguments
+            Token::Word(ref name) if self.parens_follow(line, column + name.chars().count() as u32) => {
+                let argument_count = match self.find_function_arg_count(&name.to_uppercase()) {
+                    Some(i) => i,
+                    None => parse_error!(self, UnknownFunction(name.clone())),
+                };
+                expect!(self, Token::LParens);
+                let mut arguments = Vec::new();
+                while !self.tokens.is_empty() {
+                    if let Token::RParens = self.peek() {
+                        break
+                    }
+                    arguments.push(try!(self.parse_expression()));
+                    if !self.tokens.is_empty() {
+                        if let Token::Comma = self.peek() {
+                            try!(self.pop_left());
+                        }
+                    }
+                }
+                expect!(self, Token::RParens);
+                if arguments.len() != argument_count as usize {
+                    parse_error!(self, WrongArgumentCount(name.clone(), argument_count,
+                                                          arguments.len()));
+                }
+                Ok(FuncCall(name.clone(), arguments))
+            },
             // A function call
             Token::Word(name) => {
                 let argument_count = match self.find_function_arg_count(&name.to_uppercase()) {
@@ -440,12 +603,16 @@ impl Parser {
             },
             Token::String(string) => Ok(StringLiteral(string)),
             Token::Number(num) => Ok(Number(num)),
-            // Unary prefixes for numbers
+            // Unary minus, negative number literals are kept as numbers
             Token::OpMinus => {
-                match try!(self.pop_left()) {
-                    Token::Number(num) => Ok(Number(-num)),
-                    token => parse_error!(self, UnexpectedToken("Token::Number", token)),
+                if self.tokens.is_empty() {
+                    parse_error!(self, UnexpectedEnd);
                 }
+                if let Token::Number(num) = self.peek() {
+                    try!(self.pop_left());
+                    return Ok(Number(-num));
+                }
+                Ok(Negate(Box::new(try!(self.parse_factor()))))
             },
             Token::OpPlus => {
                 match try!(self.pop_left()) {
@@ -457,3 +624,99 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{FuncMap, Parser, ParseResult};
+    use super::ast::{Node, AddOp, MulOp};
+    use super::ast::Node::*;
+    use super::super::lex;
+
+    fn parse(source: &str) -> ParseResult {
+        let tokens = lex::tokenize(source).unwrap();
+        let mut functions = FuncMap::new();
+        functions.insert("PRINT".to_owned(), 1);
+        functions.insert("SIN".to_owned(), 1);
+        Parser::new(tokens, functions).parse().map(Node::flatten)
+    }
+
+    fn print(value: f32) -> Node {
+        FuncCall("PRINT".to_owned(), vec![Number(value)])
+    }
+
+    fn var(name: &str) -> Box<Node> {
+        Box::new(Variable(name.to_owned()))
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(parse("IF :a DO PRINT 1 END").unwrap(),
+                   IfStatement(var("a"), Box::new(print(1.)), None));
+    }
+
+    #[test]
+    fn test_if_else() {
+        assert_eq!(parse("IF :a DO PRINT 1 ELSE PRINT 2 END").unwrap(),
+                   IfStatement(var("a"), Box::new(print(1.)), Some(Box::new(print(2.)))));
+    }
+
+    #[test]
+    fn test_elseif_chain() {
+        let expected = IfStatement(var("a"), Box::new(print(1.)), Some(Box::new(
+            IfStatement(var("b"), Box::new(print(2.)), Some(Box::new(print(3.)))))));
+        assert_eq!(parse("IF :a DO PRINT 1 ELSEIF :b DO PRINT 2 ELSE PRINT 3 END").unwrap(),
+                   expected);
+        assert_eq!(parse(&expected.to_string()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_nested_if_in_else() {
+        let expected = IfStatement(var("a"), Box::new(print(1.)), Some(Box::new(StatementList(vec![
+            IfStatement(var("b"), Box::new(print(2.)), None),
+            print(3.),
+        ]))));
+        assert_eq!(parse("IF :a DO PRINT 1 ELSE IF :b DO PRINT 2 END PRINT 3 END").unwrap(),
+                   expected);
+        assert!(parse("IF :a DO PRINT 1 ELSE IF :b DO PRINT 2 END").is_err());
+    }
+
+    #[test]
+    fn test_negate() {
+        assert_eq!(parse("-:x").unwrap(), Negate(var("x")));
+        let sum = Addition(Box::new(Number(1.)), vec![(AddOp::Add, Number(2.))]);
+        assert_eq!(parse("-(1 + 2)").unwrap(), Negate(Box::new(sum)));
+        assert_eq!(parse("- sin 30").unwrap(),
+                   Negate(Box::new(FuncCall("sin".to_owned(), vec![Number(30.)]))));
+        assert_eq!(parse("2 - -3").unwrap(),
+                   Addition(Box::new(Number(2.)), vec![(AddOp::Sub, Number(-3.))]));
+        assert_eq!(parse("2 - -:x").unwrap(),
+                   Addition(Box::new(Number(2.)), vec![(AddOp::Sub, Negate(var("x")))]));
+        assert!(parse("-").is_err());
+        assert!(parse("(-)").is_err());
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let expected = StringLiteral("tab\tnull\0quote\"backslash\\".to_owned());
+        assert_eq!(parse(&expected.to_string()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_list_comprehension() {
+        let expected = ListComprehension("i".to_owned(), Box::new(Number(1.)), Box::new(Number(3.)),
+                                         Box::new(Multiplication(var("i"),
+                                                                 vec![(MulOp::Mul, Number(2.))])));
+        assert_eq!(parse("[FOR :i FROM 1 TO 3 DO :i * 2 END]").unwrap(), expected);
+        assert_eq!(parse(&expected.to_string()).unwrap(), expected);
+        // FROM and TO are only keywords inside the comprehension
+        assert_eq!(parse("[:from :to]").unwrap(), List(vec![*var("from"), *var("to")]));
+    }
+
+    #[test]
+    fn test_list_comprehension_errors() {
+        assert!(parse("[FOR i FROM 1 TO 3 DO :i END]").is_err());
+        assert!(parse("[FOR :i 1 TO 3 DO :i END]").is_err());
+        assert!(parse("[FOR :i FROM 1 TO 3 DO :i]").is_err());
+        assert!(parse("[FOR :i FROM 1 TO 3 DO :i END").is_err());
+    }
+}
@@ -20,15 +20,36 @@
 //!     turtle.right(90.0);
 //! }
 //! ```
-use super::graphic::TurtleScreen;
+use super::graphic::{TurtleScreen, TurtleShape, LineStyle, Alignment, ShapeKind,
+                     DEFAULT_FONT_SIZE};
 use super::graphic::color;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 enum PenState {
     PenUp,
     PenDown,
 }
 
+/// A color gradient that is applied to the next lines the turtle draws
+#[derive(Debug, Copy, Clone)]
+struct Gradient {
+    from: color::Color,
+    to: color::Color,
+    length: f32,
+    travelled: f32,
+}
+
+/// The state of the turtle before an action, used to undo that action
+#[derive(Debug, Copy, Clone)]
+struct Snapshot {
+    orientation: f32,
+    position: (f32, f32),
+    color: color::Color,
+    pen: PenState,
+    gradient: Option<Gradient>,
+    shape_count: usize,
+}
+
 /// The `Turtle` struct is the thing that actually provides the methods to walk
 /// on the screen
 pub struct Turtle {
@@ -37,6 +58,13 @@ pub struct Turtle {
     position: (f32, f32),
     color: color::Color,
     pen: PenState,
+    line_style: LineStyle,
+    fill_tolerance: f32,
+    alignment: Alignment,
+    trace: bool,
+    immediate_draw: bool,
+    gradient: Option<Gradient>,
+    history: Vec<Snapshot>,
 }
 
 impl Turtle {
@@ -48,6 +76,13 @@ impl Turtle {
             position: (0.0, 0.0),
             color: color::BLACK,
             pen: PenState::PenDown,
+            line_style: LineStyle::Solid,
+            fill_tolerance: 0.0,
+            alignment: Alignment::Left,
+            trace: false,
+            immediate_draw: true,
+            gradient: None,
+            history: Vec::new(),
         }
     }
 
@@ -56,12 +91,108 @@ impl Turtle {
     /// implement everything else
     fn goto(&mut self, x: f32, y: f32) {
         let start_position = self.position;
+        if self.trace {
+            // Show the segment highlighted first, it is drawn in its real color
+            // afterwards
+            self.screen.trace_segment = Some((start_position, (x, y)));
+            self.update();
+            self.screen.trace_segment = None;
+        }
         if let PenState::PenDown = self.pen {
-            self.screen.add_line(start_position, (x, y), self.color);
+            if self.gradient.is_some() {
+                self.add_gradient_line(start_position, (x, y));
+            } else {
+                self.screen.add_styled_line(start_position, (x, y), self.color, self.line_style);
+            }
         }
         self.position = (x, y);
         self.screen.turtle_position = self.position;
-        self.screen.draw_and_update();
+        self.update();
+    }
+
+    /// Draw the line from `start` to `end` in short pieces, each with the
+    /// color of the gradient at that point. If the gradient is finished, the
+    /// turtle keeps its final color.
+    fn add_gradient_line(&mut self, start: (f32, f32), end: (f32, f32)) {
+        // Length of the pieces the line is split into
+        const STEP: f32 = 2.0;
+        let mut gradient = self.gradient.take().expect("no gradient set");
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = dx.hypot(dy);
+        // The pieces are added as a single line, so that one move is one shape
+        let mut pieces = Vec::new();
+        let mut done = 0.0;
+        while done < length {
+            let remaining = gradient.length - gradient.travelled;
+            let step = STEP.min(length - done).min(remaining.max(0.0));
+            if step <= 0.0 {
+                // The gradient is over, the rest is drawn in the final color
+                let from = (start.0 + dx * done / length, start.1 + dy * done / length);
+                pieces.push((from, end, gradient.to));
+                break;
+            }
+            let t = (gradient.travelled + step / 2.0) / gradient.length;
+            let (r1, g1, b1, a1) = gradient.from;
+            let (r2, g2, b2, a2) = gradient.to;
+            self.color = (r1 + (r2 - r1) * t, g1 + (g2 - g1) * t, b1 + (b2 - b1) * t,
+                          a1 + (a2 - a1) * t);
+            let from = (start.0 + dx * done / length, start.1 + dy * done / length);
+            let to = (start.0 + dx * (done + step) / length, start.1 + dy * (done + step) / length);
+            pieces.push((from, to, self.color));
+            done += step;
+            gradient.travelled += step;
+        }
+        self.screen.add_line_pieces(&pieces);
+        if gradient.travelled < gradient.length {
+            self.gradient = Some(gradient);
+        } else {
+            self.color = gradient.to;
+        }
+        self.screen.turtle_color = self.color;
+    }
+
+    /// Redraw the screen, unless immediate drawing is turned off
+    fn update(&mut self) {
+        if self.immediate_draw {
+            self.screen.draw_and_update();
+        }
+    }
+
+    /// Save the current state so that the following action can be undone
+    fn record(&mut self) {
+        let snapshot = Snapshot {
+            orientation: self.orientation,
+            position: self.position,
+            color: self.color,
+            pen: self.pen,
+            gradient: self.gradient,
+            shape_count: self.screen.shape_count(),
+        };
+        self.history.push(snapshot);
+    }
+
+    /// Undo the last action, i.e. remove what it has drawn and restore the
+    /// position, orientation, color, pen state and gradient from before that
+    /// action. Calling it repeatedly walks back through the history. Returns
+    /// false if there is nothing to undo.
+    ///
+    /// Note that `clear` also clears the history, so you can't undo past it.
+    pub fn undo(&mut self) -> bool {
+        let snapshot = match self.history.pop() {
+            Some(s) => s,
+            None => return false,
+        };
+        self.screen.truncate_shapes(snapshot.shape_count);
+        self.orientation = snapshot.orientation;
+        self.position = snapshot.position;
+        self.color = snapshot.color;
+        self.pen = snapshot.pen;
+        self.gradient = snapshot.gradient;
+        self.screen.turtle_orientation = self.orientation;
+        self.screen.turtle_position = self.position;
+        self.screen.turtle_color = self.color;
+        self.update();
+        true
     }
 
     /// Return a reference to the underlaying `TurtleScreen` object
@@ -74,7 +205,15 @@ impl Turtle {
     /// is used internally.
     fn turn(&mut self, deg: f32) {
         let orientation = self.orientation;
-        self.set_orientation(orientation + deg);
+        self.orient(orientation + deg);
+    }
+
+    /// Set the orientation without recording it in the history. This function
+    /// is used internally.
+    fn orient(&mut self, deg: f32) {
+        self.orientation = deg % 360.0;
+        self.screen.turtle_orientation = self.orientation;
+        self.update();
     }
 
     /// Take the length of a path and return the (delta_x, delta_y) attributes
@@ -89,11 +228,48 @@ impl Turtle {
     /// Clear the screen. Note that this only removes the drawn lines, it does
     /// not change the turtle's position or orientation.
     pub fn clear(&mut self) {
+        self.history.clear();
         self.screen.clear();
     }
 
+    /// Remove only the shapes of the given kind from the screen, e.g. all texts.
+    /// Like `clear`, this clears the history.
+    pub fn clear_kind(&mut self, kind: ShapeKind) {
+        self.history.clear();
+        self.screen.clear_kind(kind);
+    }
+
+    /// Reset the turtle to its initial state: Clear the screen, move the turtle
+    /// back to the origin facing north and restore the default color, pen,
+    /// line style, text alignment and fill tolerance. The turtle is shown again
+    /// and tracing is turned off.
+    ///
+    /// The background color is left alone, use `set_background_color` to
+    /// change it.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.screen.clear();
+        self.position = (0.0, 0.0);
+        self.orientation = 0.0;
+        self.color = color::BLACK;
+        self.pen = PenState::PenDown;
+        self.line_style = LineStyle::Solid;
+        self.fill_tolerance = 0.0;
+        self.alignment = Alignment::Left;
+        self.trace = false;
+        self.gradient = None;
+        self.screen.turtle_position = self.position;
+        self.screen.turtle_orientation = self.orientation;
+        self.screen.turtle_color = self.color;
+        self.screen.turtle_hidden = false;
+        self.screen.turtle_shape = TurtleShape::Ferris;
+        self.screen.turtle_scale = 1.0;
+        self.update();
+    }
+
     /// Move the turtle forward by the given length
     pub fn forward(&mut self, length: f32) {
+        self.record();
         let (x, y) = self.position;
         let (dx, dy) = self.length_to_vector(length);
         self.goto(x + dx, y + dy);
@@ -101,6 +277,7 @@ impl Turtle {
 
     /// Move the turtle backward by the given length
     pub fn backward(&mut self, length: f32) {
+        self.record();
         let (x, y) = self.position;
         let (dx, dy) = self.length_to_vector(length);
         self.goto(x - dx, y - dy);
@@ -108,38 +285,187 @@ impl Turtle {
 
     /// Turn the turtle left
     pub fn left(&mut self, deg: f32) {
+        self.record();
         self.turn(deg);
     }
 
     /// Turn the turtle right
     pub fn right(&mut self, deg: f32) {
+        self.record();
         self.turn(-deg);
     }
 
+    /// Draw a regular polygon with the given number of sides, turning right
+    /// after each side. Afterwards the turtle is back at its starting point
+    /// with its original orientation. The whole polygon is undone at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sides` is less than 3.
+    pub fn polygon(&mut self, sides: u32, side_length: f32) {
+        assert!(sides >= 3, "a polygon needs at least 3 sides");
+        self.record();
+        let start_position = self.position;
+        let start_orientation = self.orientation;
+        let angle = 360.0 / sides as f32;
+        for i in 1..sides {
+            let (x, y) = self.position;
+            let (dx, dy) = self.length_to_vector(side_length);
+            self.goto(x + dx, y + dy);
+            self.orient(start_orientation - i as f32 * angle);
+        }
+        // Go back to the exact start to avoid a gap due to rounding errors
+        self.goto(start_position.0, start_position.1);
+        self.orient(start_orientation);
+    }
+
+    /// Draw a quadratic Bezier curve from the current position to `end`, bent
+    /// towards `control`. Afterwards the turtle is at `end`, facing along the
+    /// curve. The whole curve is undone at once.
+    pub fn curve_to(&mut self, control: (f32, f32), end: (f32, f32)) {
+        // Roughly the length of one line segment of the curve
+        const SEGMENT_LENGTH: f32 = 4.0;
+        const MAX_SEGMENTS: f32 = 200.0;
+        fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+            (b.0 - a.0).hypot(b.1 - a.1)
+        }
+
+        self.record();
+        let start = self.position;
+        // The curve is never longer than its control polygon
+        let extent = distance(start, control) + distance(control, end);
+        let segments = (extent / SEGMENT_LENGTH).ceil().max(1.0).min(MAX_SEGMENTS) as u32;
+        for i in 1..segments {
+            let t = i as f32 / segments as f32;
+            let (a, b, c) = ((1. - t) * (1. - t), 2. * (1. - t) * t, t * t);
+            self.goto(a * start.0 + b * control.0 + c * end.0,
+                      a * start.1 + b * control.1 + c * end.1);
+        }
+        self.goto(end.0, end.1);
+        // The tangent at the end points from the control point to the end. If
+        // both are the same, the curve is a straight line from the start.
+        let (dx, dy) = if control != end {
+            (end.0 - control.0, end.1 - control.1)
+        } else {
+            (end.0 - start.0, end.1 - start.1)
+        };
+        if dx != 0. || dy != 0. {
+            self.orient((-dx).atan2(dy).to_degrees());
+        }
+    }
+
     /// "Lifts" the pen so that no lines are drawn anymore
     pub fn pen_up(&mut self) {
+        self.record();
         self.pen = PenState::PenUp;
     }
 
     /// Sinks the pen again so that lines are drawn
     pub fn pen_down(&mut self) {
+        self.record();
         self.pen = PenState::PenDown;
     }
 
+    /// Returns true if the pen is down, i.e. if lines are drawn
+    pub fn is_pen_down(&self) -> bool {
+        match self.pen {
+            PenState::PenDown => true,
+            PenState::PenUp => false,
+        }
+    }
+
     /// Set the turtle's color. New lines will be drawn using that color but
     /// existing lines will remain in their color. `red`, `green` and `blue` are
     /// given as floats in the range [0; 1], where 0 means nothing and 1 full
     /// (like #FF in HTML).
     pub fn set_color(&mut self, red: f32, green: f32, blue: f32) {
-        self.color = (red, green, blue, 1.0);
+        self.set_color_alpha(red, green, blue, 1.0);
+    }
+
+    /// Like `set_color`, but also set the alpha channel, where 0 is fully
+    /// transparent and 1 fully opaque.
+    pub fn set_color_alpha(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.record();
+        self.gradient = None;
+        self.color = (red, green, blue, alpha);
+        self.screen.turtle_color = self.color;
+        self.update();
+    }
+
+    /// Draw the lines of the next `length` steps with a color that changes
+    /// gradually from `from` to `to`. Afterwards the turtle keeps the color
+    /// `to`. Lines drawn with a gradient are always solid, setting a color
+    /// stops the gradient.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is not positive.
+    pub fn set_gradient(&mut self, from: color::Color, to: color::Color, length: f32) {
+        assert!(length > 0.0, "gradient length has to be positive");
+        self.record();
+        self.gradient = Some(Gradient { from: from, to: to, length: length, travelled: 0.0 });
+        self.color = from;
         self.screen.turtle_color = self.color;
-        self.screen.draw_and_update();
+        self.update();
+    }
+
+    /// Set the style in which new lines are drawn. Existing lines keep their
+    /// style.
+    pub fn set_line_style(&mut self, style: LineStyle) {
+        self.line_style = style;
+    }
+
+    /// Set the shape that the turtle is drawn as. Stamps that are already on
+    /// the screen keep their shape.
+    pub fn set_shape(&mut self, shape: TurtleShape) {
+        self.screen.turtle_shape = shape;
+        self.update();
+    }
+
+    /// Set the factor by which the turtle is scaled when drawn. 1.0 is the
+    /// normal size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is not positive.
+    pub fn set_turtle_size(&mut self, size: f32) {
+        assert!(size > 0.0, "turtle size has to be positive");
+        self.screen.turtle_scale = size;
+        self.update();
+    }
+
+    /// Set whether the screen is redrawn after every single action (the
+    /// default). Turning it off makes drawing many small lines a lot faster,
+    /// the drawing is then only shown when the screen is drawn by someone else
+    /// or when immediate drawing is turned on again.
+    pub fn set_immediate_draw(&mut self, immediate: bool) {
+        self.immediate_draw = immediate;
+        self.update();
+    }
+
+    /// Return true if the screen is redrawn after every action
+    pub fn is_immediate_draw(&self) -> bool {
+        self.immediate_draw
+    }
+
+    /// Enable or disable tracing. If tracing is enabled, each movement is
+    /// first shown as a highlighted segment before it is drawn in the turtle's
+    /// color. This makes it easier to follow where the turtle is going.
+    pub fn set_pen_trace(&mut self, trace: bool) {
+        self.trace = trace;
     }
 
     /// Set the background color of the screen.
     pub fn set_background_color(&mut self, red: f32, green: f32, blue: f32) {
-        self.screen.background_color = (red, green, blue, 1.);
-        self.screen.draw_and_update();
+        self.set_background_alpha(red, green, blue, 1.);
+    }
+
+    /// Like `set_background_color`, but also set the alpha channel. A
+    /// transparent background is kept in screenshots if the window provides an
+    /// alpha channel.
+    pub fn set_background_alpha(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.screen.background_color = (red, green, blue, alpha);
+        self.update();
     }
 
     /// Directly move the turtle to the given point without changing the
@@ -147,21 +473,30 @@ impl Turtle {
     /// is in the center of the screen with positive coordinates being right/top
     /// and negative ones left/down.
     pub fn teleport(&mut self, x: f32, y: f32) {
+        self.record();
         self.goto(x, y)
     }
 
+    /// Move the turtle by the given offset without changing the direction,
+    /// positive `dx`/`dy` move right/up. Draws a line if the pen is down.
+    pub fn move_by(&mut self, dx: f32, dy: f32) {
+        self.record();
+        let (x, y) = self.position;
+        self.goto(x + dx, y + dy)
+    }
+
     /// Set the turtle's orientation in degrees with 0 being faced north and
     /// positive degrees counting counter-clockwise.
     pub fn set_orientation(&mut self, deg: f32) {
-        self.orientation = deg % 360.0;
-        self.screen.turtle_orientation = self.orientation;
-        self.screen.draw_and_update();
+        self.record();
+        self.orient(deg);
     }
 
     /// Move the turtle to the origin and set its orientation to 0
     pub fn home(&mut self) {
-        self.teleport(0.0, 0.0);
-        self.set_orientation(0.0);
+        self.record();
+        self.goto(0.0, 0.0);
+        self.orient(0.0);
     }
 
     /// Return the turtle's orientation
@@ -172,13 +507,13 @@ impl Turtle {
     /// Hide the turtle so it won't be drawn on the screen
     pub fn hide(&mut self) {
         self.screen.turtle_hidden = true;
-        self.screen.draw_and_update();
+        self.update();
     }
 
     /// Show the turtle again after it has been hidden
     pub fn show(&mut self) {
         self.screen.turtle_hidden = false;
-        self.screen.draw_and_update();
+        self.update();
     }
 
     /// Returns true if the turtle is currently hidden
@@ -186,14 +521,77 @@ impl Turtle {
         self.screen.turtle_hidden
     }
 
-    /// Write the text on the screen. The lower-left corner of the Text starts
+    /// Write the text on the screen. The text is aligned according to
+    /// `set_alignment`, by default the lower-left corner of the text starts
     /// where the turtle is.
     pub fn write(&mut self, text: &str) {
-        self.screen.add_text(self.position, self.orientation, self.color, text);
+        let alignment = self.alignment;
+        self.write_ex(text, DEFAULT_FONT_SIZE, alignment);
+    }
+
+    /// Write the text on the screen with the given font size and alignment.
+    /// The baseline of the text is at the turtle's position, the alignment
+    /// decides whether the text starts, is centered or ends there.
+    pub fn write_ex(&mut self, text: &str, size: f32, alignment: Alignment) {
+        self.record();
+        self.screen.add_text_ex(self.position, self.orientation, self.color, text, size,
+                                alignment);
+        self.update();
+    }
+
+    /// Write the text at the given position with the given angle (in degrees,
+    /// counter-clockwise, 0 is horizontal). The turtle doesn't move, only its
+    /// color and alignment are used.
+    pub fn write_at(&mut self, position: (f32, f32), angle: f32, text: &str) {
+        self.record();
+        self.screen.add_text_ex(position, angle, self.color, text, DEFAULT_FONT_SIZE,
+                                self.alignment);
+        self.update();
+    }
+
+    /// Set the alignment that `write` uses
+    pub fn set_alignment(&mut self, alignment: Alignment) {
+        self.alignment = alignment;
+    }
+
+    /// Return the alignment that `write` uses
+    pub fn get_alignment(&self) -> Alignment {
+        self.alignment
+    }
+
+    /// Leave a copy of the turtle at its current position and orientation on
+    /// the screen
+    pub fn stamp(&mut self) {
+        self.record();
+        self.screen.add_stamp(self.position, self.orientation);
+        self.update();
+    }
+
+    /// Perform a floodfill at the current turtle position. Returns false if the
+    /// turtle is outside of the visible canvas, in which case nothing is filled.
+    pub fn flood(&mut self) -> bool {
+        let position = self.position;
+        self.flood_at(position)
+    }
+
+    /// Perform a floodfill at the given point with the turtle's color. Returns
+    /// false if the point is outside of the visible canvas, in which case
+    /// nothing is filled.
+    pub fn flood_at(&mut self, point: (f32, f32)) -> bool {
+        self.record();
+        if self.screen.floodfill(point, self.color, self.fill_tolerance) {
+            true
+        } else {
+            self.history.pop();
+            false
+        }
     }
 
-    /// Perform a floodfill at the current turtle position
-    pub fn flood(&mut self) {
-        self.screen.floodfill(self.position, self.color);
+    /// Set how much the color of a pixel may differ from the color at the
+    /// starting point to still be filled by `flood`. The tolerance is given in
+    /// the range [0; 1], where 0 (the default) only fills pixels of exactly the
+    /// same color. A small tolerance helps with the smooth edges of lines.
+    pub fn set_fill_tolerance(&mut self, tolerance: f32) {
+        self.fill_tolerance = tolerance;
     }
 }